@@ -0,0 +1,326 @@
+use std::{collections::HashMap, fmt, path::Path};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    components::{Ability, Cooldowns, GameState, Player},
+    controls::Action,
+};
+
+pub const ATTACK_FILE: &str = "abilities/attack.ability.ron";
+pub const RANGED_FILE: &str = "abilities/ranged.ability.ron";
+pub const DASH_FILE: &str = "abilities/dash.ability.ron";
+pub const BLADESTORM_FILE: &str = "abilities/bladestorm.ability.ron";
+// Loaded in this order, which is also the order of the cooldown bar
+const ABILITY_FILES: [&str; 4] = [ATTACK_FILE, RANGED_FILE, DASH_FILE, BLADESTORM_FILE];
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AbilityShape {
+    // Cone in front of the player, range is the radius
+    Sector { half_angle: f32 },
+    // Full circle around the player, range is the radius
+    Circle,
+    // Thrown blade that flies range units, then breaks or comes back
+    Projectile {
+        length: f32,
+        width: f32,
+        speed: f32,
+        #[serde(default)]
+        boomerang: bool,
+        #[serde(default)]
+        pierce: Option<usize>, // Enemies hit before it breaks, None pierces everything
+    },
+    // Sweeps the player up to range units towards the cursor over the lifetime
+    Dash { radius: f32 },
+}
+
+// Everything needed to cast an ability, read from assets/abilities
+#[derive(Asset, TypePath, Deserialize, Clone, Debug)]
+pub struct AbilityDef {
+    pub name: String,
+    pub action: Action, // What casts it, bound to keys and buttons through the InputMap
+    pub cooldown: f32,
+    #[serde(default = "default_charges")]
+    pub charges: u32,
+    pub damage: i32,
+    #[serde(default)]
+    pub knockback: f32,
+    #[serde(default)]
+    pub stun: f32,
+    pub range: f32,
+    pub lifetime: f32, // Seconds the hitbox stays out
+    pub shape: AbilityShape,
+    #[serde(default)]
+    pub sfx: Vec<String>, // One is picked at random on every cast
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+}
+
+fn default_charges() -> u32 {
+    1
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+impl AbilityDef {
+    pub fn parse_ron(source: &[u8]) -> Result<Self, AbilityDefError> {
+        ron::de::from_bytes::<Self>(source).map_err(AbilityDefError::Ron)?.validated()
+    }
+
+    pub fn parse_json(source: &[u8]) -> Result<Self, AbilityDefError> {
+        serde_json::from_slice::<Self>(source).map_err(AbilityDefError::Json)?.validated()
+    }
+
+    // Refuses values that would panic further down (timers can't take negative or infinite
+    // seconds), so a typo while hot reloading keeps the previous definition instead
+    fn validated(self) -> Result<Self, AbilityDefError> {
+        let positive = |value: f32| value.is_finite() && value > 0.0;
+        let not_negative = |value: f32| value.is_finite() && value >= 0.0;
+
+        let problem = if !positive(self.cooldown) {
+            Some("cooldown must be above 0")
+        } else if !not_negative(self.lifetime) {
+            Some("lifetime can't be negative")
+        } else if !positive(self.range) {
+            Some("range must be above 0")
+        } else if self.charges == 0 {
+            Some("charges must be at least 1")
+        } else if !not_negative(self.knockback) || !not_negative(self.stun) || !not_negative(self.volume) {
+            Some("knockback, stun and volume can't be negative")
+        } else {
+            match self.shape {
+                AbilityShape::Sector { half_angle } if !positive(half_angle) => Some("half_angle must be above 0"),
+                AbilityShape::Projectile { length, width, speed, .. }
+                    if !positive(length) || !positive(width) || !positive(speed) =>
+                {
+                    Some("projectile length, width and speed must be above 0")
+                }
+                AbilityShape::Dash { radius } if !positive(radius) => Some("dash radius must be above 0"),
+                _ => None,
+            }
+        };
+
+        match problem {
+            Some(problem) => Err(AbilityDefError::Invalid(format!("{}: {}", self.name, problem))),
+            None => Ok(self),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AbilityDefError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+    Json(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for AbilityDefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbilityDefError::Io(error) => write!(f, "could not read ability: {}", error),
+            AbilityDefError::Ron(error) => write!(f, "invalid ability RON: {}", error),
+            AbilityDefError::Json(error) => write!(f, "invalid ability JSON: {}", error),
+            AbilityDefError::Invalid(problem) => write!(f, "invalid ability {}", problem),
+        }
+    }
+}
+
+impl std::error::Error for AbilityDefError {}
+
+#[derive(Default)]
+struct AbilityDefLoader;
+
+impl AssetLoader for AbilityDefLoader {
+    type Asset = AbilityDef;
+    type Settings = ();
+    type Error = AbilityDefError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<AbilityDef, AbilityDefError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await.map_err(AbilityDefError::Io)?;
+
+        if load_context.path().to_string_lossy().ends_with(".json") {
+            AbilityDef::parse_json(&bytes)
+        } else {
+            AbilityDef::parse_ron(&bytes)
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ability.ron", "ability.json"]
+    }
+}
+
+// Changes stacked on top of an ability's definition for the rest of the run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AbilityModifier {
+    pub cooldown: f32,  // Multiplier
+    pub range: f32,     // Multiplier
+    pub knockback: f32, // Multiplier
+    pub damage: i32,
+    pub pierce: usize,
+    pub charges: u32,
+}
+
+impl Default for AbilityModifier {
+    fn default() -> Self {
+        Self {
+            cooldown: 1.0,
+            range: 1.0,
+            knockback: 1.0,
+            damage: 0,
+            pierce: 0,
+            charges: 0,
+        }
+    }
+}
+
+impl AbilityModifier {
+    pub fn apply(&self, def: &AbilityDef) -> AbilityDef {
+        let mut def = def.clone();
+        def.cooldown *= self.cooldown;
+        def.range *= self.range;
+        def.knockback *= self.knockback;
+        def.damage += self.damage;
+        def.charges += self.charges;
+
+        // Unlimited pierce already cuts through everything, the draft never offers more of it
+        if let AbilityShape::Projectile { pierce: Some(pierce), .. } = &mut def.shape {
+            *pierce += self.pierce;
+        }
+        def
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct AbilityModifiers(pub HashMap<Ability, AbilityModifier>);
+
+impl AbilityModifiers {
+    // The definition as it plays right now, with every upgrade taken so far
+    pub fn effective(&self, ability: Ability, def: &AbilityDef) -> AbilityDef {
+        self.0
+            .get(&ability)
+            .map_or_else(|| def.clone(), |modifier| modifier.apply(def))
+    }
+}
+
+// Handles to every ability the player has, in cooldown bar order
+#[derive(Resource, Default)]
+pub struct AbilityBook {
+    pub abilities: Vec<Handle<AbilityDef>>,
+}
+
+impl AbilityBook {
+    // Whether every definition in the book has finished loading
+    pub fn is_loaded(&self, defs: &Assets<AbilityDef>) -> bool {
+        self.abilities.iter().all(|handle| defs.contains(handle))
+    }
+
+    // The ability loaded from `file`, one of the *_FILE paths
+    pub fn find(&self, file: &str) -> Option<&Handle<AbilityDef>> {
+        self.abilities
+            .iter()
+            .find(|handle| handle.path().is_some_and(|path| path.path() == Path::new(file)))
+    }
+}
+
+pub struct AbilitiesPlugin;
+
+impl Plugin for AbilitiesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AbilityDef>()
+            .init_asset_loader::<AbilityDefLoader>()
+            .init_resource::<AbilityBook>()
+            .init_resource::<AbilityModifiers>()
+            .add_systems(Startup, load_abilities)
+            .add_systems(OnEnter(GameState::Reset), clear_modifiers)
+            .add_systems(FixedUpdate, sync_cooldowns);
+    }
+}
+
+fn load_abilities(mut book: ResMut<AbilityBook>, asset_server: Res<AssetServer>) {
+    book.abilities = ABILITY_FILES.iter().map(|path| asset_server.load(*path)).collect();
+}
+
+// Keeps cooldown timers in step with the definitions, including ones edited while the game runs
+fn sync_cooldowns(
+    book: Res<AbilityBook>,
+    defs: Res<Assets<AbilityDef>>,
+    modifiers: Res<AbilityModifiers>,
+    mut cooldowns_query: Query<&mut Cooldowns, With<Player>>,
+) {
+    for mut cooldowns in cooldowns_query.iter_mut() {
+        for handle in book.abilities.iter() {
+            if let Some(def) = defs.get(handle) {
+                let def = modifiers.effective(handle.id(), def);
+                cooldowns.configure(handle.id(), def.cooldown, def.charges);
+            }
+        }
+    }
+}
+
+fn clear_modifiers(mut modifiers: ResMut<AbilityModifiers>) {
+    modifiers.0.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_abilities_parse() {
+        for path in ABILITY_FILES {
+            let source = std::fs::read(format!("assets/{}", path)).unwrap();
+            let def = AbilityDef::parse_ron(&source).unwrap();
+            assert!(def.cooldown > 0.0, "{}", path);
+        }
+    }
+
+    #[test]
+    fn json_definitions_use_the_same_fields() {
+        let def = AbilityDef::parse_json(
+            br#"{
+                "name": "Spin",
+                "action": "Aoe",
+                "cooldown": 2.0,
+                "damage": 3,
+                "range": 150.0,
+                "lifetime": 0.2,
+                "shape": "Circle"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(def.action, Action::Aoe);
+        assert_eq!(def.charges, 1);
+        assert_eq!(def.shape, AbilityShape::Circle);
+        assert!(def.sfx.is_empty());
+    }
+
+    #[test]
+    fn values_that_would_panic_are_refused() {
+        let attack = std::fs::read_to_string("assets/abilities/attack.ability.ron").unwrap();
+        let negative_cooldown = attack.replace("cooldown: 1.0", "cooldown: -1.0");
+        assert!(matches!(
+            AbilityDef::parse_ron(negative_cooldown.as_bytes()),
+            Err(AbilityDefError::Invalid(_))
+        ));
+
+        let ranged = std::fs::read_to_string("assets/abilities/ranged.ability.ron").unwrap();
+        for broken in [ranged.replace("speed: 1600.0", "speed: 0.0"), ranged.replace("lifetime: 1.4", "lifetime: -1.0")] {
+            assert!(matches!(AbilityDef::parse_ron(broken.as_bytes()), Err(AbilityDefError::Invalid(_))));
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::ops::Range;
+
+use bevy::{
+    audio::{AudioBundle, AudioSink, PlaybackSettings},
+    prelude::*,
+};
+
+use crate::{
+    components::{BeatIndicator, BeatTrack, GameState},
+    Beat,
+};
+
+// Music the clock follows, its tempo is read from the file name
+const TRACK: &str = "beats/@JABBI - @hmlfortrizz - Japenese 159 BPM..ogg";
+// Seconds into the track where the first beat lands
+const TRACK_OFFSET: f32 = 0.0;
+// Seconds the track runs before looping, not a whole number of beats so every loop shifts the grid
+const TRACK_LENGTH: f32 = 49.711;
+// Fallback for tracks that don't say their tempo
+const DEFAULT_BPM: f32 = 120.0;
+// Casts this many seconds either side of a beat count as on-beat
+pub const BEAT_WINDOW: f32 = 0.1;
+pub const BEAT_BONUS_DAMAGE: i32 = 1;
+// Share of the cooldown handed back for an on-beat cast
+pub const BEAT_COOLDOWN_REFUND: f32 = 0.25;
+const INDICATOR_SIZE: f32 = 36.0;
+
+// Reads the tempo out of names like "Japenese 159 BPM" or "Exotic Jah 150bpm"
+pub fn bpm_from_name(name: &str) -> Option<f32> {
+    let lower = name.to_lowercase();
+    let end = lower.find("bpm")?;
+    let digits: String = lower[..end]
+        .trim_end()
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+
+    digits.parse().ok().filter(|bpm| *bpm > 0.0)
+}
+
+// Where the music is, measured in beats
+#[derive(Resource, Clone, Debug)]
+pub struct BeatClock {
+    pub bpm: f32,
+    pub offset: f32,  // Seconds into the track where the first beat lands
+    pub length: f32,  // Seconds before the track loops back to the start
+    pub elapsed: f32, // Seconds into the current loop of the track
+    beats: u64,       // Beats announced in the current loop
+    announced: u64,   // Beats announced since the clock started
+}
+
+impl Default for BeatClock {
+    fn default() -> Self {
+        Self::new(bpm_from_name(TRACK).unwrap_or(DEFAULT_BPM), TRACK_OFFSET, TRACK_LENGTH)
+    }
+}
+
+impl BeatClock {
+    pub fn new(bpm: f32, offset: f32, length: f32) -> Self {
+        Self {
+            bpm,
+            offset,
+            length,
+            elapsed: 0.0,
+            beats: 0,
+            announced: 0,
+        }
+    }
+
+    pub fn seconds_per_beat(&self) -> f32 {
+        60.0 / self.bpm
+    }
+
+    // Beats in a loop that land at or before `seconds`
+    fn beats_by(&self, seconds: f32) -> u64 {
+        if seconds < self.offset {
+            return 0;
+        }
+        ((seconds - self.offset) / self.seconds_per_beat()) as u64 + 1
+    }
+
+    // Moves the clock forward and returns the indices of every beat crossed on the way
+    pub fn advance(&mut self, seconds: f32) -> Range<u64> {
+        let start = self.announced;
+        self.elapsed += seconds;
+
+        // The beats left in a loop still count before the music starts over from the top
+        while self.elapsed >= self.length {
+            self.announced += self.beats_by(self.length) - self.beats;
+            self.elapsed -= self.length;
+            self.beats = 0;
+        }
+
+        let beats = self.beats_by(self.elapsed);
+        self.announced += beats - self.beats;
+        self.beats = beats;
+        start..self.announced
+    }
+
+    // Jumps straight to `elapsed` without announcing the beats skipped over, used by replays
+    pub fn seek(&mut self, elapsed: f32) {
+        self.elapsed = elapsed;
+        self.beats = self.beats_by(elapsed);
+    }
+
+    // Back to the top of the track, the next advance announces its first beat again
+    pub fn restart(&mut self) {
+        self.elapsed = 0.0;
+        self.beats = 0;
+    }
+
+    // How far through the current beat the music is, from 0 on the beat up to 1
+    pub fn phase(&self) -> f32 {
+        ((self.elapsed - self.offset) / self.seconds_per_beat()).rem_euclid(1.0)
+    }
+
+    // Seconds to the nearest beat, whether it just passed or is coming up
+    pub fn distance_to_beat(&self) -> f32 {
+        if self.elapsed < self.offset {
+            return self.offset - self.elapsed;
+        }
+
+        let into_beat = self.phase() * self.seconds_per_beat();
+        into_beat.min(self.seconds_per_beat() - into_beat)
+    }
+
+    pub fn on_beat(&self, window: f32) -> bool {
+        self.distance_to_beat() <= window
+    }
+}
+
+pub struct BeatPlugin;
+
+impl Plugin for BeatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BeatClock>()
+            .add_event::<Beat>()
+            .add_systems(Startup, play_beat_track)
+            // The music never stops, so neither does the clock
+            .add_systems(FixedUpdate, (sync_to_track, advance_beat_clock).chain())
+            .add_systems(Update, pulse_beat_indicator.run_if(in_state(GameState::Running)));
+    }
+}
+
+fn play_beat_track(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        AudioBundle {
+            source: asset_server.load(TRACK),
+            settings: PlaybackSettings::LOOP,
+        },
+        BeatTrack,
+    ));
+}
+
+// The track only starts once it has loaded, which is when its sink shows up
+fn sync_to_track(mut clock: ResMut<BeatClock>, started_query: Query<(), (With<BeatTrack>, Added<AudioSink>)>) {
+    if !started_query.is_empty() {
+        clock.restart();
+    }
+}
+
+fn advance_beat_clock(time: Res<Time>, mut clock: ResMut<BeatClock>, mut beat_writer: EventWriter<Beat>) {
+    for index in clock.advance(time.delta_seconds()) {
+        beat_writer.send(Beat { index });
+    }
+}
+
+// Swells on every beat and lights up while a cast would land on-beat
+fn pulse_beat_indicator(
+    clock: Res<BeatClock>,
+    time: Res<Time>,
+    mut beat_reader: EventReader<Beat>,
+    mut since_beat: Local<f32>,
+    mut query: Query<(&mut Style, &mut BackgroundColor), With<BeatIndicator>>,
+) {
+    *since_beat += time.delta_seconds();
+    if beat_reader.read().last().is_some() {
+        *since_beat = 0.0;
+    }
+
+    let swell = (1.0 - *since_beat / clock.seconds_per_beat()).max(0.0).powi(2);
+    let size = Val::Px(INDICATOR_SIZE * (1.0 + 0.5 * swell));
+    let color = if clock.on_beat(BEAT_WINDOW) {
+        Color::srgb(1.0, 0.8, 0.2)
+    } else {
+        Color::srgba(0.9, 0.9, 0.9, 0.4)
+    };
+
+    for (mut style, mut background) in query.iter_mut() {
+        style.width = size;
+        style.height = size;
+        *background = color.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tempo_comes_from_the_track_names() {
+        assert_eq!(bpm_from_name("@JABBI - @hmlfortrizz - Japenese 159 BPM..ogg"), Some(159.0));
+        assert_eq!(bpm_from_name("Exotic Jah 150bpm @wheresrx @ayojabbi @helcorpmusic.ogg"), Some(150.0));
+        assert_eq!(bpm_from_name("back.ogg"), None);
+    }
+
+    #[test]
+    fn clock_announces_each_beat_once_and_knows_the_window() {
+        // One beat every half second, the first landing at 0.25s
+        let mut clock = BeatClock::new(120.0, 0.25, 10.0);
+
+        assert_eq!(clock.advance(0.2), 0..0);
+        assert!(clock.on_beat(0.1));
+        assert_eq!(clock.advance(0.1), 0..1);
+        assert_eq!(clock.advance(0.1), 1..1);
+        assert!(!clock.on_beat(0.1));
+
+        // A long frame crosses several beats at once
+        assert_eq!(clock.advance(1.0), 1..3);
+        assert!((clock.distance_to_beat() - 0.15).abs() < 1e-4);
+    }
+
+    #[test]
+    fn clock_rephases_when_the_track_loops() {
+        // Beats at 0, 0.5, 1.0 and 1.5, then the track starts over at 1.75
+        let mut clock = BeatClock::new(120.0, 0.0, 1.75);
+
+        assert_eq!(clock.advance(1.6), 0..4);
+        assert_eq!(clock.advance(0.2), 4..5);
+        assert!((clock.elapsed - 0.05).abs() < 1e-4);
+        assert!(clock.on_beat(0.1));
+
+        // Restarting lands back on the first beat
+        clock.advance(0.3);
+        clock.restart();
+        assert_eq!(clock.advance(0.0), 5..6);
+        assert!(clock.phase() < 1e-4);
+    }
+}
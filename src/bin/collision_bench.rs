@@ -0,0 +1,75 @@
+// Headless timing of the collision pipeline, run with `cargo run --release --bin collision_bench`
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use gmtk_gamejam::{
+    collision::{build_broadphase, detect_collisions, Broadphase},
+    components::{Collider, CollisionLayer, CollisionLayers},
+    events::CollisionEvent,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const COLLIDER_COUNTS: [usize; 3] = [1_000, 5_000, 10_000];
+const TICKS: u32 = 100;
+// Average spacing between colliders, the arena grows with the count so density stays the same
+const SPACING: f32 = 60.;
+// Share of colliders that are ability hitboxes rather than enemies
+const HITBOX_SHARE: f64 = 0.3;
+
+fn build_app(count: usize) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Broadphase::new(128.))
+        .add_event::<CollisionEvent>()
+        .add_systems(FixedUpdate, (build_broadphase, detect_collisions).chain());
+
+    let mut rng = StdRng::seed_from_u64(count as u64);
+    let half_extent = (count as f32).sqrt() * SPACING / 2.;
+
+    app.world_mut().spawn((
+        CollisionLayers::new(CollisionLayer::Player),
+        Transform::default(),
+        Collider::new(Vec2::splat(56.)),
+    ));
+
+    for _ in 0..count {
+        let position = Vec2::new(
+            rng.gen_range(-half_extent..half_extent),
+            rng.gen_range(-half_extent..half_extent),
+        );
+        let transform = Transform::from_translation(position.extend(0.));
+
+        if rng.gen_bool(HITBOX_SHARE) {
+            app.world_mut().spawn((CollisionLayers::new(CollisionLayer::PlayerHitbox), transform, Collider::new(Vec2::splat(5.))));
+        } else {
+            app.world_mut().spawn((CollisionLayers::new(CollisionLayer::Enemy), transform, Collider::new(Vec2::splat(112.5))));
+        }
+    }
+
+    app
+}
+
+fn main() {
+    println!("{:>10} {:>14} {:>14}", "colliders", "avg tick", "pairs/tick");
+
+    for count in COLLIDER_COUNTS {
+        let mut app = build_app(count);
+
+        // Warm up allocations in the grid before timing
+        app.world_mut().run_schedule(FixedUpdate);
+
+        let mut total = Duration::ZERO;
+        for _ in 0..TICKS {
+            let start = Instant::now();
+            app.world_mut().run_schedule(FixedUpdate);
+            total += start.elapsed();
+
+            app.world_mut().resource_mut::<Events<CollisionEvent>>().clear();
+        }
+
+        let mut colliders = app.world_mut().query::<&Collider>();
+        let pairs: usize = colliders.iter(app.world()).map(|collider| collider.collisions.len()).sum();
+
+        println!("{:>10} {:>14?} {:>14}", count, total / TICKS, pairs);
+    }
+}
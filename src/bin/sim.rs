@@ -0,0 +1,89 @@
+// Plays a scripted run headless and checks how it went, run with
+// `cargo run --release --bin sim -- [seconds] [--seed N] [--min-score N] [--alive]`
+use std::process::ExitCode;
+
+use bevy::prelude::*;
+use gmtk_gamejam::sim::{headless_app, run_ticks, InputScript, ScriptedInput, SimReport, TICKS_PER_SECOND};
+
+const DEFAULT_SECONDS: u32 = 120;
+// Seconds between progress lines
+const REPORT_EVERY: u32 = 10;
+// Distance the scripted cursor is held from the arena centre
+const AIM_DISTANCE: f32 = 400.;
+
+// Circles the arena while casting everything on cooldown and always taking the first upgrade
+fn autopilot(seconds: u32) -> InputScript {
+    let mut script = InputScript::default();
+    let moves = [KeyCode::KeyW, KeyCode::KeyD, KeyCode::KeyS, KeyCode::KeyA];
+
+    for second in 0..seconds {
+        let tick = second * TICKS_PER_SECOND;
+        let key = moves[(second / 2) as usize % moves.len()];
+        script.hold(tick..tick + TICKS_PER_SECOND, key);
+
+        let aim = Vec2::from_angle(second as f32) * AIM_DISTANCE;
+        script
+            .at(tick, ScriptedInput::Aim(aim))
+            .tap(tick, KeyCode::KeyQ)
+            .tap(tick + 8, KeyCode::KeyE)
+            .tap(tick + 16, KeyCode::KeyT)
+            .tap(tick + 24, KeyCode::KeyF)
+            .tap(tick + 32, KeyCode::Digit1);
+    }
+    script
+}
+
+const USAGE: &str = "usage: sim [seconds] [--seed N] [--min-score N] [--alive]";
+
+fn main() -> ExitCode {
+    let mut seconds = DEFAULT_SECONDS;
+    let mut seed = 0;
+    let mut min_score = 0;
+    let mut must_survive = false;
+
+    // A typo must not quietly turn into a check that always passes
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let problem = match arg.as_str() {
+            "--seed" => args.next().and_then(|value| value.parse().ok()).map(|value| seed = value).is_none(),
+            "--min-score" => args.next().and_then(|value| value.parse().ok()).map(|value| min_score = value).is_none(),
+            "--alive" => {
+                must_survive = true;
+                false
+            }
+            _ => arg.parse().ok().map(|value| seconds = value).is_none(),
+        };
+        if problem {
+            match arg.as_str() {
+                "--seed" | "--min-score" => eprintln!("{} needs a number after it", arg),
+                _ => eprintln!("unknown argument {}", arg),
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    println!("seed {}", seed);
+    let mut app = headless_app(seed, autopilot(seconds));
+    for second in (0..seconds).step_by(REPORT_EVERY as usize) {
+        run_ticks(&mut app, REPORT_EVERY.min(seconds - second) * TICKS_PER_SECOND);
+        println!("{:>4}s {}", (second + REPORT_EVERY).min(seconds), SimReport::of(&mut app));
+    }
+
+    let report = SimReport::of(&mut app);
+    let mut failed = false;
+    if report.points < min_score {
+        eprintln!("score {} is below the expected {}", report.points, min_score);
+        failed = true;
+    }
+    if must_survive && report.health.is_none_or(|hp| hp <= 0) {
+        eprintln!("the player did not survive {}s", seconds);
+        failed = true;
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -1,97 +1,185 @@
 use std::collections::HashMap;
 use bevy::prelude::*;
-use crate::{components::{Collider, Enemy, Health, Invulnerability, Line, Player, PointMarker}, CollisionEvent, ENEMY_SPEED};
+use crate::{
+    narrowphase::WorldShape,
+    spatial::SpatialHash,
+    components::{Collider, CollisionLayer, CollisionLayers, GameState},
+    CollisionEvent,
+};
+
+// Broadphase cell size, a bit larger than a regular oni collider
+const BROADPHASE_CELL_SIZE: f32 = 128.;
 
 pub struct CollisionPlugin;
 
 impl Plugin for CollisionPlugin {
     fn build(&self, app: &mut App) {
-         app.add_systems(Update, (detect_collisions, handle_collisions,));
-    }    
+        app.insert_resource(Broadphase::new(BROADPHASE_CELL_SIZE))
+            // Overlaps found while paused would land as stale hits on resume
+            .add_systems(
+                FixedUpdate,
+                (build_broadphase, detect_collisions)
+                    .chain()
+                    .run_if(in_state(GameState::Running)),
+            );
+    }
 }
 
-fn detect_collisions(
-    mut query: Query<(Entity, &Transform, &mut Collider, Option<&Player>, Option<&Enemy>, Option<&Line>, Option<&PointMarker>), Without<Invulnerability>>,
-    mut events: EventWriter<CollisionEvent>,
-) {
-    let mut collisions: HashMap<Entity, Vec<Entity>> = HashMap::new();
+// Colliders bucketed per layer, rebuilt every fixed tick
+struct LayerGrid {
+    hash: SpatialHash<(Entity, WorldShape)>,
+    max_half_size: Vec2, // Largest collider in the layer, widens queries so big colliders are not missed
+}
 
-    for (entity_a, transform_a, collider_a, player_a, _, line_a, point_marker_a) in query.iter() {
-        let rect_a = Rect::from_center_size(transform_a.translation.truncate(), collider_a.size);
+#[derive(Resource)]
+pub struct Broadphase {
+    cell_size: f32,
+    layers: HashMap<CollisionLayer, LayerGrid>,
+}
 
-        for (entity_b, transform_b, collider_b, _, enemy_b, _, _) in query.iter() {
-            let rect_b = Rect::from_center_size(transform_b.translation.truncate(), collider_b.size);
+impl Broadphase {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            layers: HashMap::new(),
+        }
+    }
 
-            if entity_b == entity_a {
-                continue;
-            }
+    pub fn clear(&mut self) {
+        for grid in self.layers.values_mut() {
+            grid.hash.clear();
+            grid.max_half_size = Vec2::ZERO;
+        }
+    }
 
-            if !rect_a.intersect(rect_b).is_empty() {
-                if player_a.is_some() && enemy_b.is_some() {
-                    events.send(CollisionEvent::Collision);
+    pub fn insert(&mut self, layer: CollisionLayer, entity: Entity, shape: WorldShape) {
+        let bounds = shape.bounds();
+        let cell_size = self.cell_size;
+        let grid = self.layers.entry(layer).or_insert_with(|| LayerGrid {
+            hash: SpatialHash::new(cell_size),
+            max_half_size: Vec2::ZERO,
+        });
 
-                    collisions.entry(entity_a).or_default().push(entity_b);
+        grid.hash.insert(bounds.center(), (entity, shape));
+        grid.max_half_size = grid.max_half_size.max(bounds.half_size());
+    }
 
-                } else if line_a.is_some() && enemy_b.is_some() {
-                    events.send(CollisionEvent::Damage(entity_a));
+    // Every collider on `layer` that touches `shape`
+    pub fn overlapping(&self, layer: CollisionLayer, shape: WorldShape) -> impl Iterator<Item = Entity> + '_ {
+        let rect = shape.bounds();
+
+        self.layers
+            .get(&layer)
+            .into_iter()
+            .flat_map(move |grid| {
+                let reach = (rect.half_size() + grid.max_half_size).max_element();
+                grid.hash.query(rect.center(), reach)
+            })
+            .filter(move |(_, (_, other))| {
+                !rect.intersect(other.bounds()).is_empty() && shape.intersects(other)
+            })
+            .map(|(_, (entity, _))| *entity)
+    }
+}
 
-                    collisions.entry(entity_a).or_default().push(entity_b);
+pub fn build_broadphase(
+    mut broadphase: ResMut<Broadphase>,
+    query: Query<(Entity, &Transform, &Collider, &CollisionLayers)>,
+) {
+    broadphase.clear();
 
-                } else if point_marker_a.is_some() && enemy_b.is_some() {
-                    events.send(CollisionEvent::Damage(entity_a));
+    for (entity, transform, collider, layers) in query.iter() {
+        broadphase.insert(layers.member, entity, WorldShape::from_collider(collider.shape, transform));
+    }
+}
 
-                    collisions.entry(entity_a).or_default().push(entity_b);
-                }
-            }
+pub fn detect_collisions(
+    broadphase: Res<Broadphase>,
+    mut query: Query<(Entity, &Transform, &mut Collider, &CollisionLayers)>,
+    mut events: EventWriter<CollisionEvent>,
+) {
+    for (entity, transform, mut collider, layers) in query.iter_mut() {
+        collider.collisions.clear();
+
+        if layers.filter.is_empty() {
+            continue;
         }
 
-    }
+        let shape = WorldShape::from_collider(collider.shape, transform);
 
-    for(entity, _, mut collider, _, _, _, _) in query.iter_mut() {
-        collider.collisions = collisions.remove(&entity).unwrap_or_default();
+        for other_layer in layers.filter.iter() {
+            for other in broadphase.overlapping(other_layer, shape) {
+                if other == entity {
+                    continue;
+                }
+
+                collider.collisions.push(other);
+                events.send(CollisionEvent {
+                    entity,
+                    layer: layers.member,
+                    other,
+                    other_layer,
+                });
+            }
+        }
     }
 }
 
-fn handle_collisions(
-    mut collision_reader: EventReader<CollisionEvent>,
-    entity_query: Query<&Collider, Without<Player>>,
-    mut player_query: Query<(&mut Collider, &mut Transform, &mut Health), With<Player>>,
-    transform_query: Query<&Transform, Without<Player>>,
-    time: Res<Time>,
-    mut health: Query<&mut Health, Without<Player>>,
-) {
-    for event in collision_reader.read() {
-        match event {
-            CollisionEvent::Collision => {
-                if let Ok((mut player_collider, mut player_transform, mut player_health)) = player_query.get_single_mut() {
-                    player_health.take_damage(10);
-                    let mut direction_vector = Vec3::ZERO;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                    for collision in player_collider.collisions.iter() {
-                        let enemy_transform = transform_query.get(*collision).expect("Collided with entity without collider");
+    fn aabb(center: Vec2, size: Vec2) -> WorldShape {
+        WorldShape::from_collider(Collider::new(size).shape, &Transform::from_translation(center.extend(0.)))
+    }
 
-                        let direction = (player_transform.translation - enemy_transform.translation).normalize();
+    #[test]
+    fn overlapping_only_returns_the_queried_layer() {
+        let mut broadphase = Broadphase::new(BROADPHASE_CELL_SIZE);
+        let enemy = Entity::from_raw(1);
+        let hitbox = Entity::from_raw(2);
+        broadphase.insert(CollisionLayer::Enemy, enemy, aabb(Vec2::ZERO, Vec2::splat(50.)));
+        broadphase.insert(CollisionLayer::PlayerHitbox, hitbox, aabb(Vec2::ZERO, Vec2::splat(50.)));
 
-                        direction_vector += direction;
-                    }
+        let probe = aabb(Vec2::new(30., 0.), Vec2::splat(20.));
+        assert_eq!(broadphase.overlapping(CollisionLayer::Enemy, probe).collect::<Vec<_>>(), vec![enemy]);
 
-                    player_collider.collisions.clear();
+        let miss = aabb(Vec2::new(100., 0.), Vec2::splat(20.));
+        assert_eq!(broadphase.overlapping(CollisionLayer::Enemy, miss).count(), 0);
+    }
 
-                    player_transform.translation += direction_vector * time.delta_seconds() * ENEMY_SPEED;
+    #[test]
+    fn large_colliders_are_found_from_distant_cells() {
+        let mut broadphase = Broadphase::new(BROADPHASE_CELL_SIZE);
+        let line = Entity::from_raw(1);
+        broadphase.insert(CollisionLayer::PlayerHitbox, line, aabb(Vec2::ZERO, Vec2::new(1100., 20.)));
 
-                }
-            }
-            CollisionEvent::Damage(entity) => {
-                info!("Damage collision detected");
-                if let Ok(entity_collider) = entity_query.get(*entity) {
-                    for collisions in entity_collider.collisions.iter() {
-                        if let Ok(mut other_entity_health) = health.get_mut(*collisions) {
-                            other_entity_health.take_damage(1);
-                        }
-                    }
-                }
-               
-            }
-        }    
+        let probe = aabb(Vec2::new(500., 0.), Vec2::splat(10.));
+        assert_eq!(broadphase.overlapping(CollisionLayer::PlayerHitbox, probe).collect::<Vec<_>>(), vec![line]);
+    }
+
+    #[test]
+    fn events_follow_the_interaction_table() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(Broadphase::new(BROADPHASE_CELL_SIZE))
+            .add_event::<CollisionEvent>()
+            .add_systems(Update, (build_broadphase, detect_collisions).chain());
+
+        let mut spawn = |layer| {
+            app.world_mut()
+                .spawn((CollisionLayers::new(layer), Transform::default(), Collider::new(Vec2::splat(10.))))
+                .id()
+        };
+        let hitbox = spawn(CollisionLayer::PlayerHitbox);
+        let enemy = spawn(CollisionLayer::Enemy);
+        spawn(CollisionLayer::Pickup);
+
+        app.update();
+
+        let events: Vec<CollisionEvent> = app.world_mut().resource_mut::<Events<CollisionEvent>>().drain().collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].entity, events[0].layer), (hitbox, CollisionLayer::PlayerHitbox));
+        assert_eq!((events[0].other, events[0].other_layer), (enemy, CollisionLayer::Enemy));
     }
 }
@@ -1,12 +1,15 @@
+use crate::{abilities::AbilityDef, controls::Action};
 use bevy::{
-    asset::Handle,
+    asset::{AssetId, Handle},
     ecs::entity::Entity,
+    log::debug,
     prelude::{Component, Resource, Timer, TimerMode, Vec2},
     render::texture::Image,
     state::state::States,
     utils::HashSet,
 };
-use std::{collections::HashMap, fmt, time::Duration};
+use serde::Deserialize;
+use std::{collections::HashMap, time::Duration};
 
 // Menu enum
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
@@ -15,6 +18,7 @@ pub enum GameState {
     Menu,
     Running,
     Paused,
+    LevelUp, // Game is frozen while the player picks an upgrade
     Reset,
     GameOver,
     Won,
@@ -23,20 +27,114 @@ pub enum GameState {
 // Common Components
 #[derive(Component)]
 pub struct Collider{
-    pub size: Vec2,
+    pub shape: ColliderShape,
     pub collisions: Vec<Entity>,
 }
 
 impl Collider {
+    // Axis aligned box of the given full size
     pub fn new(size: Vec2) -> Self {
+        Self::from_shape(ColliderShape::Aabb { half_size: size / 2. })
+    }
+
+    pub fn from_shape(shape: ColliderShape) -> Self {
         Self {
-            size,
+            shape,
             collisions: vec![],
         }
     }
 
 }
 
+// Collider geometry in world units, oriented shapes follow Transform.rotation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColliderShape {
+    Aabb { half_size: Vec2 },
+    Obb { half_size: Vec2 },
+    Circle { radius: f32 },
+    Capsule { half_length: f32, radius: f32 }, // Along the local x axis, a radius of 0 is a plain segment
+    Sector { radius: f32, half_angle: f32 },    // Pie slice centred on the local x axis
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CollisionLayer {
+    Player,
+    Enemy,
+    PlayerHitbox,
+    Boss,
+    EnemyProjectile,
+    Pickup,
+    Hazard,
+}
+
+impl CollisionLayer {
+    pub const ALL: [CollisionLayer; 7] = [
+        CollisionLayer::Player,
+        CollisionLayer::Enemy,
+        CollisionLayer::PlayerHitbox,
+        CollisionLayer::Boss,
+        CollisionLayer::EnemyProjectile,
+        CollisionLayer::Pickup,
+        CollisionLayer::Hazard,
+    ];
+}
+
+// Which layers each layer reacts to, the reacting side is the one that receives the CollisionEvent
+const LAYER_INTERACTIONS: &[(CollisionLayer, &[CollisionLayer])] = &[
+    (CollisionLayer::Player, &[CollisionLayer::Enemy]),
+    (CollisionLayer::PlayerHitbox, &[CollisionLayer::Enemy, CollisionLayer::Boss]),
+    (CollisionLayer::EnemyProjectile, &[CollisionLayer::Player]),
+    (CollisionLayer::Hazard, &[CollisionLayer::Player, CollisionLayer::Enemy]),
+    (CollisionLayer::Pickup, &[CollisionLayer::Player]),
+];
+
+// Set of collision layers packed into bits
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LayerMask(u32);
+
+impl LayerMask {
+    pub const NONE: LayerMask = LayerMask(0);
+
+    pub fn from_layers(layers: &[CollisionLayer]) -> Self {
+        layers.iter().fold(Self::NONE, |mask, layer| mask.with(*layer))
+    }
+
+    pub fn with(self, layer: CollisionLayer) -> Self {
+        LayerMask(self.0 | 1 << layer as u32)
+    }
+
+    pub fn contains(self, layer: CollisionLayer) -> bool {
+        self.0 & 1 << layer as u32 != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = CollisionLayer> {
+        CollisionLayer::ALL.into_iter().filter(move |layer| self.contains(*layer))
+    }
+}
+
+// Layer a collider lives on and the layers it looks for
+#[derive(Component, Clone, Copy, Debug)]
+pub struct CollisionLayers {
+    pub member: CollisionLayer,
+    pub filter: LayerMask,
+}
+
+impl CollisionLayers {
+    // Member of `layer`, reacting to everything the interaction table allows
+    pub fn new(member: CollisionLayer) -> Self {
+        let filter = LAYER_INTERACTIONS
+            .iter()
+            .find(|(layer, _)| *layer == member)
+            .map_or(LayerMask::NONE, |(_, targets)| LayerMask::from_layers(targets));
+
+        Self { member, filter }
+    }
+}
+
 #[derive(Component)]
 pub struct Health {
     pub hp: i32
@@ -45,7 +143,11 @@ pub struct Health {
 impl Health {
     pub fn take_damage(&mut self, amount: i32) {
         self.hp -= amount;
-        println!("Damage Taken: {}", amount);
+        debug!("Damage taken: {}", amount);
+    }
+
+    pub fn heal(&mut self, amount: i32, max: i32) {
+        self.hp = (self.hp + amount).min(max);
     }
 }
 
@@ -55,7 +157,15 @@ pub struct Velocity {
     pub y: f32,
 }
 #[derive(Component)]
-pub struct CooldownUi;
+pub struct CooldownUi(pub Ability);
+
+// HUD light that pulses with the music
+#[derive(Component)]
+pub struct BeatIndicator;
+
+// The looping track the beat clock follows
+#[derive(Component)]
+pub struct BeatTrack;
 
 
 // Player Components
@@ -65,24 +175,35 @@ pub struct Line;
 #[derive(Component)]
 pub struct Player;
 
-#[derive(Resource)]
+// Player is mid-dash, moving one step per fixed tick while the hitbox covers the path so far
+#[derive(Component)]
+pub struct Dash {
+    pub origin: Vec2,
+    pub step: Vec2,
+    pub ticks_left: u32,
+    pub radius: f32,
+    pub hitbox: Entity,
+}
+
 pub struct SpawnTimer {
     pub timer: Timer,
     pub interval_decrease: f32,
+    pub min_interval: Duration,
 }
 
 impl SpawnTimer {
-    pub fn new(initial_duration: Duration, interval_decrease: f32) -> Self {
+    pub fn new(initial_duration: Duration, interval_decrease: f32, min_interval: Duration) -> Self {
         Self {
             timer: Timer::new(initial_duration, TimerMode::Repeating),
             interval_decrease,
+            min_interval,
         }
     }
 
     pub fn update(&mut self, delta: Duration) {
         // Decrease the interval by the specified amount (2 milliseconds per second)
         let decrease = self.interval_decrease * delta.as_secs_f32();
-        let new_duration = (self.timer.duration().as_secs_f32() - decrease).max(0.001); // Ensure the duration doesn't go below 1ms
+        let new_duration = (self.timer.duration().as_secs_f32() - decrease).max(self.min_interval.as_secs_f32()); // Ensure the duration doesn't go below the minimum
         self.timer.set_duration(Duration::from_secs_f32(new_duration));
         self.timer.tick(delta);
     }
@@ -91,106 +212,208 @@ impl SpawnTimer {
 #[derive(Component)]
 pub struct Wallpaper;
 
-//#[derive(Component)]
-//pub struct Player {
-//    pub health: i32,
-//    pub x: f32,
-//    pub y: f32,
-//}
-
-//impl Player {
-//    pub fn new(health: i32) -> Self {
-//        Player { health, x: 0., y: 0. }
-//    }
-//
-//    pub fn heal(
-//        &mut self,
-//        amount: i32,
-//    ){
-//        if self.health < 500 {
-//            self.health +=amount;
-//        }
-//    }
-//
-//    pub fn take_damage(
-//        &mut self,
-//        amount: i32,
-//        entity: Entity,
-//        commands: &mut Commands,
-//        invulnerability_option: Option<&mut Invulnerability>,
-//        state: &mut ResMut<NextState<GameState>>,
-//        asset_server: & Res<AssetServer>,
-//    ) {
-//        if let Some(invulnerability) = invulnerability_option {
-//            if invulnerability.is_active() {
-//                //println!("Player is invulnerable, no damage taken.");
-//                return;
-//            } 
-//        } else {
-//            // If no invulnerability component, add it with the desired duration
-//            commands.entity(entity).insert(Invulnerability::new(1.0));
-//            // println!("Invulnerability added with duration: {} seconds.", invulnerability_duration);
-//        }
-//
-//        // Apply damage to the player
-//        self.health -= amount;
-//        println!("Player took {} damage, remaining health: {}", amount, self.health);
-//        >>>>>>> main
-//
-//            if self.health <= 0 {
-//                death_sound(asset_server, commands);
-//                state.set(GameState::GameOver);
-//            }
-//        commands.entity(entity).insert(Invulnerability::new(1.0));
-//    }
-//}
-
-#[derive(Component)]
-pub struct PointMarker;
-
 #[derive(Component)]
 pub struct Lifetime {
     pub timer: Timer,
 }
 
+// Something lying in the world that the player collects by touching it
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub enum Pickup {
+    Health(i32),
+    Xp(u32),
+    CooldownScroll, // Finishes every cooldown
+    Magnet,         // Pulls in every pickup on the map
+}
+
+// Pickup is flying towards the player
+#[derive(Component)]
+pub struct Attracted;
+
 // Enemy components
 #[derive(Component)]
 pub struct Enemy;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Component)]
-pub enum Ability {
-    Dash,
-    Attack,
-    Ranged,
-    Aoe,
-}
-impl fmt::Display for Ability {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Ability::Attack => write!(f, "Attack"),
-            Ability::Ranged => write!(f, "Ranged"),
-            Ability::Dash => write!(f, "Dash"),
-            Ability::Aoe => write!(f, "Bladestorm"),
+#[derive(Component, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum EnemyKind {
+    #[default]
+    Oni,
+    Runner,
+    Brute,
+    Spitter,
+    Splitter,
+    Splitling,
+}
+
+// Damage dealt to the player on touch, and how far the touch shoves them away
+#[derive(Component)]
+pub struct ContactDamage {
+    pub damage: i32,
+    pub knockback: f32,
+}
+
+// Enemies that keep their distance and shoot at the player
+#[derive(Component)]
+pub struct RangedAttacker {
+    pub timer: Timer,
+    pub range: f32,
+}
+
+// How an enemy approaches the player before crowd forces are blended in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SteeringBehaviour {
+    Seek,
+    Flank { angle: f32 },   // Swing out to the side by up to this many radians
+    Orbit { radius: f32 },  // Circle the player at this distance
+}
+
+// Weights for blending seek, separation and cohesion into a heading
+#[derive(Component, Clone, Copy)]
+pub struct Steering {
+    pub seek: f32,
+    pub separation: f32,
+    pub cohesion: f32,
+    pub behaviour: SteeringBehaviour,
+    pub bias: f32, // Radians drawn at spawn, picks the flank side and pulls stacked enemies apart
+}
+
+#[derive(Component)]
+pub struct EnemyProjectile {
+    pub damage: i32,
+}
+
+// Hitbox damage, turned into a DamageEvent for every target it touches
+#[derive(Component)]
+pub struct DamageOnHit {
+    pub amount: i32,
+    pub kind: Ability,
+    pub knockback: f32, // Initial push speed given to the target
+    pub stun: f32,      // Seconds the target stops steering
+}
+
+// Push from a hit, bleeds off through friction
+#[derive(Component)]
+pub struct Knockback {
+    pub velocity: Vec2,
+}
+
+// Stops an enemy from steering, moving on its own or attacking
+#[derive(Component)]
+pub struct Stunned {
+    pub timer: Timer,
+}
+
+// Targets a hitbox already damaged, so one swing only hits each enemy once.
+// A limit caps how many targets it can hit at all, which is how projectiles pierce
+#[derive(Component, Default)]
+pub struct HitRegistry {
+    pub hit: HashSet<Entity>,
+    pub limit: Option<usize>,
+}
+
+impl HitRegistry {
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            hit: HashSet::new(),
+            limit: Some(limit),
         }
     }
+
+    // True the first time a target is seen, while there is room left
+    pub fn register(&mut self, target: Entity) -> bool {
+        !self.is_full() && self.hit.insert(target)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.limit.is_some_and(|limit| self.hit.len() >= limit)
+    }
+}
+
+// Flies along its direction every tick
+#[derive(Component)]
+pub struct Projectile {
+    pub direction: Vec2,
+    pub speed: f32,
+}
+
+// Turns a projectile towards the nearest enemy in range
+#[derive(Component)]
+pub struct Homing {
+    pub turn_rate: f32,
+    pub range: f32,
+}
+
+// Flies out until the timer runs out, then heads back to the owner and can hit everything again
+#[derive(Component)]
+pub struct Boomerang {
+    pub owner: Entity,
+    pub timer: Timer,
+    pub returning: bool,
+}
+
+// Ability of the last hitbox that damaged this entity
+#[derive(Component)]
+pub struct LastHitBy(pub Ability);
+
+// Abilities are data files, each one is told apart by its definition asset
+pub type Ability = AssetId<AbilityDef>;
+
+// Uses banked for abilities that can store more than one, each refills over the cooldown
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Charges {
+    pub stored: u32,
+    pub max: u32,
 }
 
 #[derive(Component)]
 pub struct Cooldowns {
     pub cooldowns: HashMap<Ability, Timer>,
+    pub charges: HashMap<Ability, Charges>,
+}
+
+impl Default for Cooldowns {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Cooldowns {
+    // Starts empty, abilities are added as their definitions load
     pub fn new() -> Self {
-        let mut cooldowns = HashMap::new();
-        cooldowns.insert(Ability::Dash, Timer::from_seconds(5.0, TimerMode::Once)); // 5 second cooldown
-        cooldowns.insert(Ability::Ranged, Timer::from_seconds(3.0, TimerMode::Once));
-        cooldowns.insert(Ability::Attack, Timer::from_seconds(1.0, TimerMode::Once));    // 3 second cooldown
-        cooldowns.insert(Ability::Aoe, Timer::from_seconds(10.0, TimerMode::Once)); // 10 second cooldown
-        Self { cooldowns }
+        Self {
+            cooldowns: HashMap::new(),
+            charges: HashMap::new(),
+        }
+    }
+
+    // Adds an ability or picks up a changed definition without losing progress on its timer
+    pub fn configure(&mut self, ability: Ability, seconds: f32, max_charges: u32) {
+        let duration = Duration::from_secs_f32(seconds);
+        let timer = self
+            .cooldowns
+            .entry(ability)
+            .or_insert_with(|| Timer::new(duration, TimerMode::Once));
+        if timer.duration() != duration {
+            timer.set_duration(duration);
+        }
+
+        if max_charges > 1 {
+            let charges = self.charges.entry(ability).or_insert(Charges {
+                stored: 0,
+                max: max_charges,
+            });
+            charges.max = max_charges;
+            charges.stored = charges.stored.min(max_charges);
+        } else {
+            self.charges.remove(&ability);
+        }
     }
 
     pub fn is_ready(&self, ability: Ability) -> bool {
+        if let Some(charges) = self.charges.get(&ability) {
+            return charges.stored > 0;
+        }
+
         if let Some(timer) = self.cooldowns.get(&ability) {
             timer.finished()
         } else {
@@ -198,9 +421,45 @@ impl Cooldowns {
         }
     }
 
+    // Starts the cooldown, or spends a charge for abilities that have them
     pub fn reset(&mut self, ability: Ability) {
-        if let Some(timer) = self.cooldowns.get_mut(&ability) {
-            timer.reset();
+        let Some(timer) = self.cooldowns.get_mut(&ability) else {
+            return;
+        };
+
+        match self.charges.get_mut(&ability) {
+            Some(charges) => {
+                // A full stack was not recharging, so the refill starts now
+                if charges.stored == charges.max {
+                    timer.reset();
+                }
+                charges.stored = charges.stored.saturating_sub(1);
+            }
+            None => timer.reset(),
+        }
+    }
+
+    pub fn get_charges(&self, ability: Ability) -> Option<Charges> {
+        self.charges.get(&ability).copied()
+    }
+
+    pub fn tick(&mut self, delta: Duration) {
+        for (ability, timer) in self.cooldowns.iter_mut() {
+            let Some(charges) = self.charges.get_mut(ability) else {
+                timer.tick(delta);
+                continue;
+            };
+
+            if charges.stored == charges.max {
+                continue;
+            }
+
+            if timer.tick(delta).finished() {
+                charges.stored += 1;
+                if charges.stored < charges.max {
+                    timer.reset();
+                }
+            }
         }
     }
 
@@ -217,6 +476,28 @@ impl Cooldowns {
         for timer in self.cooldowns.values_mut() {
             timer.reset();
         }
+        for charges in self.charges.values_mut() {
+            charges.stored = 0;
+        }
+    }
+
+    // Advances one ability's timer by `seconds`, never past its duration
+    pub fn refund(&mut self, ability: Ability, seconds: f32) {
+        if let Some(timer) = self.cooldowns.get_mut(&ability) {
+            let elapsed_time = timer.elapsed_secs() + seconds;
+            timer.set_elapsed(Duration::from_secs_f32(elapsed_time.min(timer.duration().as_secs_f32())));
+        }
+        self.tick(Duration::ZERO);
+    }
+
+    // Advances every timer by `seconds`, never past its duration
+    pub fn reduce_all(&mut self, seconds: f32) {
+        for timer in self.cooldowns.values_mut() {
+            let elapsed_time = timer.elapsed_secs() + seconds;
+            timer.set_elapsed(Duration::from_secs_f32(elapsed_time.min(timer.duration().as_secs_f32())));
+        }
+        // Lets charges pick up a refill that just completed
+        self.tick(Duration::ZERO);
     }
     
 }
@@ -235,29 +516,45 @@ pub struct Bigfoot {
     pub y: f32,
     pub state: BigfootState,
     pub timer: Timer,
+    pub hit_timer: Timer, // Grace period so a single swing only lands one hit
     pub health: i32,
-    pub airTexture: Handle<Image>,
-    pub groundTexture: Handle<Image>,
+    pub air_texture: Handle<Image>,
+    pub ground_texture: Handle<Image>,
 }
 
 impl Bigfoot {
-    pub fn new(x: f32, y: f32) -> Self {
+    pub fn new(x: f32, y: f32, air_texture: Handle<Image>, ground_texture: Handle<Image>) -> Self {
+        let mut hit_timer = Timer::from_seconds(0.5, TimerMode::Once);
+        hit_timer.tick(hit_timer.duration()); // Start ready to be hit
+
         Bigfoot {
             timer: Timer::from_seconds(2.5, TimerMode::Once),
             state: BigfootState::Invulnerable,
+            hit_timer,
             x,
             y,
-            health: 5,
-            airTexture: todo!(),
-            groundTexture: todo!(), // Initial health value
+            health: 5, // Initial health value
+            air_texture,
+            ground_texture,
         }
     }
 
+    pub fn can_be_hit(&self) -> bool {
+        self.state == BigfootState::Solid && self.hit_timer.finished()
+    }
+
     pub fn take_damage(&mut self, amount: i32) {
         self.health -= amount;
         if self.health < 0 {
             self.health = 0;
         }
+        self.hit_timer.reset();
+
+        if self.is_dead() {
+            // Play out the death phase before the run is won
+            self.state = BigfootState::Cleanup;
+            self.timer = Timer::from_seconds(2.0, TimerMode::Once);
+        }
     }
 
     pub fn is_dead(&self) -> bool {
@@ -275,6 +572,9 @@ pub enum BigfootState {
 #[derive(Component)]
 pub struct GameOverUI;
 
+#[derive(Component)]
+pub struct VictoryUI;
+
 
 #[derive(Component)]
 pub struct Resettable;
@@ -288,6 +588,13 @@ pub struct MenuUI;
 #[derive(Component)]
 pub struct PauseMenu;
 
+#[derive(Component)]
+pub struct LevelUpUI;
+
+// Index into the current upgrade draft
+#[derive(Component)]
+pub struct UpgradeButton(pub usize);
+
 #[derive(Component, PartialEq)]
 pub struct StartButton;
 
@@ -297,6 +604,52 @@ pub struct RestartButton;
 #[derive(Component)]
 pub struct QuitButton;
 
+#[derive(Component)]
+pub struct WatchReplayButton;
+
+#[derive(Component)]
+pub struct OptionsButton;
+
+// Root of the options screen, laid over the main menu
+#[derive(Component)]
+pub struct OptionsUI;
+
+// Opens the controls screen from the options screen
+#[derive(Component)]
+pub struct ControlsButton;
+
+#[derive(Component)]
+pub struct CloseOptionsButton;
+
+// Text spelling out the controls, rewritten whenever the bindings change
+#[derive(Component)]
+pub struct ControlsHint;
+
+// Root of the controls screen, laid over the options screen
+#[derive(Component)]
+pub struct ControlsUI;
+
+// Row button that captures a new binding for the action
+#[derive(Component)]
+pub struct RebindButton(pub Action);
+
+// Lists what the action is bound to
+#[derive(Component)]
+pub struct BindingText(pub Action);
+
+#[derive(Component)]
+pub struct ConflictText;
+
+#[derive(Component)]
+pub struct ResetControlsButton;
+
+#[derive(Component)]
+pub struct SaveControlsButton;
+
+// Action waiting on its next key or button, if any
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<Action>);
+
 #[derive(Component)]
 pub struct HealthText;
 
@@ -306,6 +659,8 @@ pub struct ScoreText;
 #[derive(Resource)]
 pub struct Score {
     pub enemies_killed: u32,
+    pub points: u32,
+    pub damage_dealt: u32,
 }
 
 #[derive(Resource)]
@@ -315,24 +670,47 @@ pub struct GameTimer(pub f32);
 pub struct GameTimerText;
 
 
+impl Default for Score {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Score {
     pub fn new() -> Self {
         Score {
             enemies_killed: 0,
+            points: 0,
+            damage_dealt: 0,
         }
     }
 
     pub fn reset(&mut self) {
         self.enemies_killed = 0;
+        self.points = 0;
+        self.damage_dealt = 0;
     }
 
-    pub fn increment(&mut self) {
+    pub fn increment(&mut self, points: u32) {
         self.enemies_killed += 1;
+        self.points += points;
     }
 
     pub fn get_enemies_killed(&self) -> u32 {
         self.enemies_killed
     }
+
+    pub fn get_points(&self) -> u32 {
+        self.points
+    }
+
+    pub fn add_damage(&mut self, amount: i32) {
+        self.damage_dealt += amount.max(0) as u32;
+    }
+
+    pub fn get_damage_dealt(&self) -> u32 {
+        self.damage_dealt
+    }
 }
 
 
@@ -346,9 +724,6 @@ pub struct MousePosition {
     pub y: f32,
 }
 
-#[derive(Default, Resource)]
-pub struct Points(pub Vec<Vec2>);
-
 #[derive(Component)]
 pub struct Invulnerability {
     pub timer: Timer,
@@ -0,0 +1,549 @@
+use std::{collections::BTreeMap, fmt, fs, io, path::PathBuf};
+
+use bevy::{
+    ecs::system::SystemParam,
+    input::{
+        gamepad::{GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads},
+        InputSystem,
+    },
+    prelude::*,
+    window::CursorMoved,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::Player,
+    replay::{GatherInput, ReplayPlayback},
+    systems::update_mouse_position,
+    MouseCoords,
+};
+
+// Sticks count as held once pushed this far
+const DEFAULT_DEADZONE: f32 = 0.3;
+// The stick aims at a point this far out from the player
+const STICK_AIM_DISTANCE: f32 = 300.;
+
+// Everything the player can do, whatever it happens to be bound to
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Melee,
+    Ranged,
+    Aoe,
+    Dash,
+    Pause,
+    Back, // Leaves the pause screen for the menu
+    Upgrade1,
+    Upgrade2,
+    Upgrade3,
+    FastForward, // Speeds up replays
+}
+
+impl Action {
+    pub const ALL: [Action; 14] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Melee,
+        Action::Ranged,
+        Action::Aoe,
+        Action::Dash,
+        Action::Pause,
+        Action::Back,
+        Action::Upgrade1,
+        Action::Upgrade2,
+        Action::Upgrade3,
+        Action::FastForward,
+    ];
+
+    // Picks from the level-up draft, in button order
+    pub const UPGRADES: [Action; 3] = [Action::Upgrade1, Action::Upgrade2, Action::Upgrade3];
+
+    // Bit for this action in ActionState and replay frames
+    pub(crate) fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Melee => "Melee",
+            Action::Ranged => "Ranged",
+            Action::Aoe => "AoE",
+            Action::Dash => "Dash",
+            Action::Pause => "Pause",
+            Action::Back => "Back to menu",
+            Action::Upgrade1 => "Upgrade 1",
+            Action::Upgrade2 => "Upgrade 2",
+            Action::Upgrade3 => "Upgrade 3",
+            Action::FastForward => "Fast forward replay",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Pad(GamepadButtonType),
+    // One direction of a stick, held past the deadzone
+    Stick { axis: GamepadAxisType, positive: bool },
+}
+
+impl Binding {
+    pub fn is_gamepad(&self) -> bool {
+        matches!(self, Binding::Pad(_) | Binding::Stick { .. })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(KeyCode::Escape) => write!(f, "Esc"),
+            Binding::Key(key) => {
+                let name = format!("{:?}", key);
+                let short = ["Key", "Digit", "Arrow"]
+                    .iter()
+                    .find_map(|prefix| name.strip_prefix(prefix))
+                    .unwrap_or(&name);
+                write!(f, "{}", short)
+            }
+            Binding::Mouse(MouseButton::Left) => write!(f, "LMB"),
+            Binding::Mouse(MouseButton::Right) => write!(f, "RMB"),
+            Binding::Mouse(MouseButton::Middle) => write!(f, "MMB"),
+            Binding::Mouse(button) => write!(f, "Mouse {:?}", button),
+            Binding::Pad(button) => write!(f, "Pad {:?}", button),
+            Binding::Stick { axis, positive } => write!(f, "{:?}{}", axis, if *positive { "+" } else { "-" }),
+        }
+    }
+}
+
+// What every action is bound to, saved to the user's config folder
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct InputMap {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+    pub aim_stick: (GamepadAxisType, GamepadAxisType), // x and y of the stick that aims
+    pub deadzone: f32,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Binding::{Key, Mouse, Pad, Stick};
+        use GamepadAxisType::{LeftStickX, LeftStickY};
+
+        let bindings = [
+            (Action::MoveUp, vec![Key(KeyCode::KeyW), Pad(GamepadButtonType::DPadUp), Stick { axis: LeftStickY, positive: true }]),
+            (Action::MoveDown, vec![Key(KeyCode::KeyS), Pad(GamepadButtonType::DPadDown), Stick { axis: LeftStickY, positive: false }]),
+            (Action::MoveLeft, vec![Key(KeyCode::KeyA), Pad(GamepadButtonType::DPadLeft), Stick { axis: LeftStickX, positive: false }]),
+            (Action::MoveRight, vec![Key(KeyCode::KeyD), Pad(GamepadButtonType::DPadRight), Stick { axis: LeftStickX, positive: true }]),
+            (Action::Melee, vec![Key(KeyCode::KeyQ), Mouse(MouseButton::Left), Pad(GamepadButtonType::RightTrigger)]),
+            (Action::Ranged, vec![Key(KeyCode::KeyE), Mouse(MouseButton::Right), Pad(GamepadButtonType::RightTrigger2)]),
+            (Action::Aoe, vec![Key(KeyCode::KeyT), Pad(GamepadButtonType::North)]),
+            (Action::Dash, vec![Key(KeyCode::KeyF), Pad(GamepadButtonType::South)]),
+            (Action::Pause, vec![Key(KeyCode::Escape), Pad(GamepadButtonType::Start)]),
+            (Action::Back, vec![Key(KeyCode::KeyB), Pad(GamepadButtonType::East)]),
+            (Action::Upgrade1, vec![Key(KeyCode::Digit1)]),
+            (Action::Upgrade2, vec![Key(KeyCode::Digit2)]),
+            (Action::Upgrade3, vec![Key(KeyCode::Digit3)]),
+            (Action::FastForward, vec![Key(KeyCode::Tab), Pad(GamepadButtonType::Select)]),
+        ];
+
+        Self {
+            bindings: bindings.into_iter().collect(),
+            aim_stick: (GamepadAxisType::RightStickX, GamepadAxisType::RightStickY),
+            deadzone: DEFAULT_DEADZONE,
+        }
+    }
+}
+
+impl InputMap {
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    // Binds `action` to `binding` in place of whatever it had on the same kind of device, keyboard
+    // and mouse or gamepad, so rebinding a key keeps the gamepad buttons and the other way round
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        bindings.retain(|bound| bound.is_gamepad() != binding.is_gamepad());
+        bindings.insert(0, binding);
+    }
+
+    // Every binding shared by two actions, with the actions in Action order
+    pub fn conflicts(&self) -> Vec<(Binding, Action, Action)> {
+        let mut conflicts = Vec::new();
+        for (index, first) in Action::ALL.iter().enumerate() {
+            for second in Action::ALL[index + 1..].iter() {
+                for binding in self.bindings(*first) {
+                    if self.bindings(*second).contains(binding) {
+                        conflicts.push((*binding, *first, *second));
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    // Name shown in hints, keyboard and mouse first since that's what the hints are read with
+    pub fn label(&self, action: Action) -> String {
+        let bindings = self.bindings(action);
+        bindings
+            .iter()
+            .find(|binding| matches!(binding, Binding::Key(_) | Binding::Mouse(_)))
+            .or(bindings.first())
+            .map_or("-".to_string(), Binding::to_string)
+    }
+
+    // The controls line shown on the menu and in game, e.g. "WASD to move | Q melee | ..."
+    pub fn hint(&self) -> String {
+        let moves = [Action::MoveUp, Action::MoveLeft, Action::MoveDown, Action::MoveRight].map(|action| self.label(action));
+        let moves = if moves.iter().all(|label| label.chars().count() == 1) {
+            moves.concat()
+        } else {
+            moves.join("/")
+        };
+
+        format!(
+            "{} to move | {} melee | {} ranged | {} AoE | {} dash",
+            moves,
+            self.label(Action::Melee),
+            self.label(Action::Ranged),
+            self.label(Action::Aoe),
+            self.label(Action::Dash),
+        )
+    }
+
+    pub fn parse_ron(source: &str) -> Result<Self, ControlsError> {
+        let mut map: Self = ron::from_str(source).map_err(ControlsError::Ron)?;
+        // Actions added since the file was written keep their default bindings
+        for (action, bindings) in Self::default().bindings {
+            map.bindings.entry(action).or_insert(bindings);
+        }
+        Ok(map)
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+pub enum ControlsError {
+    Io(io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for ControlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlsError::Io(error) => write!(f, "could not access controls: {}", error),
+            ControlsError::Ron(error) => write!(f, "invalid controls RON: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ControlsError {}
+
+// Where the bindings are saved, only set for the windowed game so tests never touch the disk
+#[derive(Resource)]
+pub struct ControlsFile(pub PathBuf);
+
+impl ControlsFile {
+    pub fn from_user_config() -> Option<Self> {
+        dirs::config_dir().map(|dir| Self(dir.join("gashadokuro").join("controls.ron")))
+    }
+
+    pub fn load(&self) -> Result<InputMap, ControlsError> {
+        InputMap::parse_ron(&fs::read_to_string(&self.0).map_err(ControlsError::Io)?)
+    }
+
+    pub fn save(&self, map: &InputMap) -> Result<(), ControlsError> {
+        if let Some(parent) = self.0.parent() {
+            fs::create_dir_all(parent).map_err(ControlsError::Io)?;
+        }
+        fs::write(&self.0, map.to_ron()).map_err(ControlsError::Io)
+    }
+
+    // The saved bindings, writing out the defaults the first time so there's a file to edit
+    pub fn load_or_create(&self) -> InputMap {
+        if !self.0.exists() {
+            let map = InputMap::default();
+            if let Err(error) = self.save(&map) {
+                warn!("Could not save {}: {}", self.0.display(), error);
+            }
+            return map;
+        }
+
+        self.load().unwrap_or_else(|error| {
+            warn!("Using default controls, {}: {}", self.0.display(), error);
+            InputMap::default()
+        })
+    }
+}
+
+// Every device the bindings can point at, read through the InputMap
+#[derive(SystemParam)]
+pub struct ActionInput<'w> {
+    pub map: Res<'w, InputMap>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    pad_buttons: Res<'w, ButtonInput<GamepadButton>>,
+    pad_axes: Res<'w, Axis<GamepadAxis>>,
+    gamepads: Res<'w, Gamepads>,
+}
+
+impl ActionInput<'_> {
+    fn axis(&self, axis: GamepadAxisType) -> f32 {
+        // Whichever connected pad is pushed furthest
+        self.gamepads
+            .iter()
+            .filter_map(|gamepad| self.pad_axes.get(GamepadAxis::new(gamepad, axis)))
+            .fold(0., |furthest, value| if value.abs() > f32::abs(furthest) { value } else { furthest })
+    }
+
+    fn binding_held(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.keys.pressed(key),
+            Binding::Mouse(button) => self.mouse.pressed(button),
+            Binding::Pad(button) => self
+                .gamepads
+                .iter()
+                .any(|gamepad| self.pad_buttons.pressed(GamepadButton::new(gamepad, button))),
+            Binding::Stick { axis, positive } => {
+                let value = self.axis(axis);
+                if positive {
+                    value > self.map.deadzone
+                } else {
+                    value < -self.map.deadzone
+                }
+            }
+        }
+    }
+
+    // Sticks have no press of their own, ActionState works those out from one tick to the next
+    fn binding_just_pressed(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.keys.just_pressed(key),
+            Binding::Mouse(button) => self.mouse.just_pressed(button),
+            Binding::Pad(button) => self
+                .gamepads
+                .iter()
+                .any(|gamepad| self.pad_buttons.just_pressed(GamepadButton::new(gamepad, button))),
+            Binding::Stick { .. } => false,
+        }
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.map.bindings(action).iter().any(|binding| self.binding_held(*binding))
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.map.bindings(action).iter().any(|binding| self.binding_just_pressed(*binding))
+    }
+
+    pub fn aim_stick(&self) -> Vec2 {
+        Vec2::new(self.axis(self.map.aim_stick.0), self.axis(self.map.aim_stick.1))
+    }
+}
+
+// Actions held and pressed this tick, what gameplay reads instead of the devices themselves
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ActionState {
+    pub held: u32,    // Bit per Action
+    pub pressed: u32, // Actions that went down this tick
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.held & action.bit() != 0
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed & action.bit() != 0
+    }
+}
+
+// Actions pressed since the last fixed tick. Presses come in once per frame, so without this a
+// press on a frame with no tick would be lost and one on a frame with two ticks would count twice.
+#[derive(Resource, Default)]
+pub struct LatchedPresses(u32);
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputMap>()
+            .init_resource::<ActionState>()
+            .init_resource::<LatchedPresses>()
+            .add_event::<CursorMoved>()
+            .add_systems(
+                PreUpdate,
+                latch_presses.after(InputSystem).run_if(not(resource_exists::<ReplayPlayback>)),
+            )
+            // The stick gets the last word on aim whenever it's been pushed
+            .add_systems(
+                FixedPreUpdate,
+                (gather_actions, aim_with_stick.after(update_mouse_position)).in_set(GatherInput),
+            );
+    }
+}
+
+pub fn latch_presses(input: ActionInput, mut latched: ResMut<LatchedPresses>) {
+    for action in Action::ALL {
+        if input.just_pressed(action) {
+            latched.0 |= action.bit();
+        }
+    }
+}
+
+// The first tick after a press gets it, whichever frame that lands in
+fn gather_actions(input: ActionInput, mut latched: ResMut<LatchedPresses>, mut actions: ResMut<ActionState>) {
+    let mut state = ActionState::default();
+    for action in Action::ALL {
+        if input.pressed(action) {
+            state.held |= action.bit();
+        }
+        // Sticks have no press of their own, so they count as pressed on the tick they cross the deadzone
+        if latched.0 & action.bit() != 0 || (state.pressed(action) && !actions.pressed(action)) {
+            state.pressed |= action.bit();
+        }
+    }
+    latched.0 = 0;
+    *actions = state;
+}
+
+// Twin-stick aiming, the stick keeps its aim until it's pushed again or the mouse moves
+fn aim_with_stick(
+    input: ActionInput,
+    mut cursor_moved: EventReader<CursorMoved>,
+    mut stick_aim: Local<Option<Vec2>>,
+    player_query: Query<&Transform, With<Player>>,
+    mut mouse_coords: ResMut<MouseCoords>,
+) {
+    if cursor_moved.read().count() > 0 {
+        *stick_aim = None;
+    }
+
+    let stick = input.aim_stick();
+    if stick.length() > input.map.deadzone {
+        *stick_aim = Some(stick.normalize());
+    }
+
+    let (Some(direction), Ok(transform)) = (*stick_aim, player_query.get_single()) else {
+        return;
+    };
+    let aim = transform.translation.truncate() + direction * STICK_AIM_DISTANCE;
+    mouse_coords.x = aim.x;
+    mouse_coords.y = aim.y;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_hint_matches_the_classic_layout() {
+        assert_eq!(InputMap::default().hint(), "WASD to move | Q melee | E ranged | T AoE | F dash");
+        assert_eq!(InputMap::default().label(Action::Pause), "Esc");
+    }
+
+    #[test]
+    fn saved_maps_round_trip_and_fill_in_new_actions() {
+        let mut map = InputMap::default();
+        map.bindings.insert(Action::Melee, vec![Binding::Key(KeyCode::KeyJ)]);
+        assert_eq!(InputMap::parse_ron(&map.to_ron()).unwrap(), map);
+
+        // An older file that only knew about melee
+        let old = InputMap::parse_ron("(bindings: {Melee: [Key(KeyJ)]})").unwrap();
+        assert_eq!(old.bindings(Action::Melee), &[Binding::Key(KeyCode::KeyJ)]);
+        assert_eq!(old.bindings(Action::Dash), InputMap::default().bindings(Action::Dash));
+        assert_eq!(old.deadzone, DEFAULT_DEADZONE);
+    }
+
+    fn input_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, ControlsPlugin))
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<ButtonInput<MouseButton>>()
+            .init_resource::<ButtonInput<GamepadButton>>()
+            .init_resource::<Axis<GamepadAxis>>()
+            .init_resource::<Gamepads>()
+            .insert_resource(MouseCoords { x: 0., y: 0. });
+        app
+    }
+
+    // One frame of input followed by `ticks` fixed ticks, returning what each tick saw
+    fn frame(app: &mut App, press: Option<KeyCode>, ticks: usize) -> Vec<ActionState> {
+        let mut keys = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+        keys.clear();
+        if let Some(key) = press {
+            keys.press(key);
+        }
+        app.world_mut().run_schedule(PreUpdate);
+
+        (0..ticks)
+            .map(|_| {
+                app.world_mut().run_schedule(FixedPreUpdate);
+                *app.world().resource::<ActionState>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rebound_keys_drive_the_actions() {
+        let mut app = input_app();
+        app.world_mut().resource_mut::<InputMap>().bindings.insert(Action::Melee, vec![Binding::Key(KeyCode::KeyJ)]);
+
+        let ticks = frame(&mut app, Some(KeyCode::KeyJ), 1);
+        assert!(ticks[0].pressed(Action::Melee) && ticks[0].just_pressed(Action::Melee));
+
+        // Still held a frame later, but no longer a fresh press
+        let ticks = frame(&mut app, None, 1);
+        assert!(ticks[0].pressed(Action::Melee) && !ticks[0].just_pressed(Action::Melee));
+
+        // The old key does nothing any more
+        app.world_mut().resource_mut::<ButtonInput<KeyCode>>().reset_all();
+        let ticks = frame(&mut app, Some(KeyCode::KeyQ), 1);
+        assert_eq!(ticks[0], ActionState::default());
+    }
+
+    #[test]
+    fn presses_land_on_exactly_one_tick() {
+        let mut app = input_app();
+
+        // A tap on a frame without a tick still reaches the next one
+        frame(&mut app, Some(KeyCode::KeyQ), 0);
+        app.world_mut().resource_mut::<ButtonInput<KeyCode>>().release(KeyCode::KeyQ);
+        let ticks = frame(&mut app, None, 1);
+        assert!(ticks[0].just_pressed(Action::Melee));
+
+        // A frame with two ticks only reports it on the first
+        let ticks = frame(&mut app, Some(KeyCode::KeyE), 2);
+        assert!(ticks[0].just_pressed(Action::Ranged));
+        assert!(!ticks[1].just_pressed(Action::Ranged) && ticks[1].pressed(Action::Ranged));
+    }
+
+    #[test]
+    fn rebinding_keeps_the_other_device_and_reports_clashes() {
+        let mut map = InputMap::default();
+        assert!(map.conflicts().is_empty());
+
+        map.rebind(Action::Dash, Binding::Key(KeyCode::KeyQ));
+        assert_eq!(map.bindings(Action::Dash), &[Binding::Key(KeyCode::KeyQ), Binding::Pad(GamepadButtonType::South)]);
+        assert_eq!(map.conflicts(), vec![(Binding::Key(KeyCode::KeyQ), Action::Melee, Action::Dash)]);
+
+        map.rebind(Action::Dash, Binding::Pad(GamepadButtonType::West));
+        assert_eq!(map.bindings(Action::Dash), &[Binding::Pad(GamepadButtonType::West), Binding::Key(KeyCode::KeyQ)]);
+    }
+}
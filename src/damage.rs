@@ -0,0 +1,494 @@
+use bevy::prelude::*;
+
+use crate::{
+    bone_hit,
+    clean_dead,
+    collision::detect_collisions,
+    components::{
+        Bigfoot, Collider, ColliderShape, CollisionLayer, ContactDamage, DamageOnHit, Enemy, GameState,
+        Health, HitRegistry, Invulnerability, Knockback, LastHitBy, Player, Resettable, Score, Stunned,
+    },
+    rng::CosmeticRng,
+    play_hit_swing, CollisionEvent, DamageApplied, DamageEvent, PlayerHit,
+};
+
+// Grace period after an enemy touches the player
+const CONTACT_INVULNERABILITY: f32 = 0.5;
+
+// Floating damage number constants
+const DAMAGE_NUMBER_LIFETIME: f32 = 0.6;
+const DAMAGE_NUMBER_RISE: f32 = 60.; // Units per second
+const DAMAGE_NUMBER_SIZE: f32 = 28.;
+
+pub struct DamagePlugin;
+
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>()
+            .add_event::<DamageApplied>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    hitbox_damage.after(detect_collisions),
+                    contact_damage.after(detect_collisions),
+                    (
+                        (apply_damage, hit_reaction, damage_bigfoot, damage_sfx),
+                        (track_damage_dealt, spawn_damage_numbers).after(apply_damage).after(damage_bigfoot),
+                    )
+                        .after(hitbox_damage)
+                        .before(clean_dead),
+                    update_damage_numbers,
+                )
+                    .run_if(in_state(GameState::Running)),
+            );
+    }
+}
+
+#[derive(Component)]
+struct DamageNumber {
+    timer: Timer,
+}
+
+// Turns hitbox overlaps into damage, once per target for hitboxes with a registry
+fn hitbox_damage(
+    mut collision_reader: EventReader<CollisionEvent>,
+    mut hitbox_query: Query<(&DamageOnHit, Option<&mut HitRegistry>)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for event in collision_reader.read() {
+        let Ok((damage, registry)) = hitbox_query.get_mut(event.entity) else {
+            continue;
+        };
+
+        if registry.is_some_and(|mut registry| !registry.register(event.other)) {
+            continue;
+        }
+
+        damage_events.send(DamageEvent {
+            source: event.entity,
+            target: event.other,
+            amount: damage.amount,
+            kind: damage.kind,
+        });
+    }
+}
+
+// Enemies touching the player land a single hit from the strongest of them, then i-frames kick in
+fn contact_damage(
+    mut collision_reader: EventReader<CollisionEvent>,
+    player_query: Query<&Transform, (With<Player>, Without<Invulnerability>)>,
+    enemy_query: Query<(&Transform, &ContactDamage), Without<Player>>,
+    mut player_hits: EventWriter<PlayerHit>,
+) {
+    let mut push = Vec2::ZERO;
+    let mut strongest: Option<&ContactDamage> = None;
+
+    for event in collision_reader.read() {
+        if event.layer != CollisionLayer::Player || event.other_layer != CollisionLayer::Enemy {
+            continue;
+        }
+
+        let (Ok(player_transform), Ok((enemy_transform, contact))) =
+            (player_query.get(event.entity), enemy_query.get(event.other))
+        else {
+            continue;
+        };
+
+        push += (player_transform.translation - enemy_transform.translation).truncate().normalize_or_zero();
+        if strongest.is_none_or(|strongest| contact.damage > strongest.damage) {
+            strongest = Some(contact);
+        }
+    }
+
+    if let Some(contact) = strongest {
+        player_hits.send(PlayerHit {
+            amount: contact.damage,
+            knockback: push.normalize_or_zero() * contact.knockback,
+            invulnerability: CONTACT_INVULNERABILITY,
+        });
+    }
+}
+
+fn apply_damage(
+    mut commands: Commands,
+    mut damage_reader: EventReader<DamageEvent>,
+    mut health_query: Query<&mut Health, Without<Player>>,
+    mut applied_events: EventWriter<DamageApplied>,
+) {
+    for damage in damage_reader.read() {
+        if let Ok(mut health) = health_query.get_mut(damage.target) {
+            // Only the health that was left counts, overkill and hits on the already dead don't
+            let applied = damage.amount.min(health.hp.max(0));
+            health.take_damage(damage.amount);
+            commands.entity(damage.target).try_insert(LastHitBy(damage.kind));
+
+            if applied > 0 {
+                applied_events.send(DamageApplied {
+                    target: damage.target,
+                    amount: applied,
+                    kind: damage.kind,
+                });
+            }
+        }
+    }
+}
+
+// Shoves enemies away from whatever hit them and stuns them if the hitbox says so
+fn hit_reaction(
+    mut commands: Commands,
+    mut damage_reader: EventReader<DamageEvent>,
+    source_query: Query<(&Transform, &Collider, &DamageOnHit)>,
+    mut enemy_query: Query<(&Transform, Option<&mut Knockback>), With<Enemy>>,
+) {
+    for damage in damage_reader.read() {
+        let (Ok((source_transform, collider, hit)), Ok((target_transform, knockback))) =
+            (source_query.get(damage.source), enemy_query.get_mut(damage.target))
+        else {
+            continue;
+        };
+
+        if hit.knockback > 0.0 {
+            let impulse = knockback_direction(source_transform, collider.shape, target_transform) * hit.knockback;
+
+            match knockback {
+                Some(mut knockback) => knockback.velocity += impulse,
+                None => {
+                    commands.entity(damage.target).try_insert(Knockback { velocity: impulse });
+                }
+            }
+        }
+
+        if hit.stun > 0.0 {
+            commands.entity(damage.target).try_insert(Stunned {
+                timer: Timer::from_seconds(hit.stun, TimerMode::Once),
+            });
+        }
+    }
+}
+
+// Lines push along their length, everything else pushes out from its center
+fn knockback_direction(source: &Transform, shape: ColliderShape, target: &Transform) -> Vec2 {
+    let facing = (source.rotation * Vec3::X).truncate();
+
+    match shape {
+        ColliderShape::Obb { .. } | ColliderShape::Capsule { .. } => facing,
+        _ => (target.translation - source.translation).truncate().try_normalize().unwrap_or(facing),
+    }
+}
+
+fn damage_bigfoot(
+    mut damage_reader: EventReader<DamageEvent>,
+    mut bigfoot_query: Query<&mut Bigfoot>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    mut applied_events: EventWriter<DamageApplied>,
+) {
+    for damage in damage_reader.read() {
+        let Ok(mut bigfoot) = bigfoot_query.get_mut(damage.target) else {
+            continue;
+        };
+
+        // Bigfoot can only be hurt while its foot is planted
+        if bigfoot.can_be_hit() {
+            let applied = damage.amount.min(bigfoot.health);
+            bigfoot.take_damage(damage.amount);
+            bone_hit(&asset_server, &mut commands);
+            info!("Bigfoot hit, remaining health: {}", bigfoot.health);
+
+            applied_events.send(DamageApplied {
+                target: damage.target,
+                amount: applied,
+                kind: damage.kind,
+            });
+        }
+    }
+}
+
+fn track_damage_dealt(
+    mut damage_reader: EventReader<DamageApplied>,
+    mut score: ResMut<Score>,
+) {
+    for damage in damage_reader.read() {
+        score.add_damage(damage.amount);
+    }
+}
+
+fn damage_sfx(
+    mut damage_reader: EventReader<DamageEvent>,
+    enemy_query: Query<(), With<Enemy>>,
+    asset_server: Res<AssetServer>,
+    mut sfx_rng: ResMut<CosmeticRng>,
+    mut commands: Commands,
+) {
+    // One sound per tick no matter how many enemies a swing catches
+    if damage_reader.read().any(|damage| enemy_query.contains(damage.target)) {
+        play_hit_swing(&asset_server, &mut commands, &mut sfx_rng.0);
+    }
+}
+
+fn spawn_damage_numbers(
+    mut commands: Commands,
+    mut damage_reader: EventReader<DamageApplied>,
+    target_query: Query<&Transform, Without<DamageNumber>>,
+) {
+    for damage in damage_reader.read() {
+        let Ok(transform) = target_query.get(damage.target) else {
+            continue;
+        };
+
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    damage.amount.to_string(),
+                    TextStyle {
+                        font_size: DAMAGE_NUMBER_SIZE,
+                        color: Color::srgb(1.0, 0.9, 0.3),
+                        ..Default::default()
+                    },
+                ),
+                transform: Transform::from_translation(transform.translation.truncate().extend(20.)),
+                ..Default::default()
+            },
+            DamageNumber {
+                timer: Timer::from_seconds(DAMAGE_NUMBER_LIFETIME, TimerMode::Once),
+            },
+            Resettable,
+        ));
+    }
+}
+
+fn update_damage_numbers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut DamageNumber, &mut Transform, &mut Text)>,
+) {
+    for (entity, mut number, mut transform, mut text) in query.iter_mut() {
+        number.timer.tick(time.delta());
+
+        if number.timer.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        transform.translation.y += DAMAGE_NUMBER_RISE * time.delta_seconds();
+        for section in text.sections.iter_mut() {
+            section.style.color.set_alpha(number.timer.fraction_remaining());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::state::app::StatesPlugin;
+
+    use super::*;
+    use crate::{
+        collision::CollisionPlugin,
+        abilities::AbilityDef,
+        components::{Ability, CollisionLayers},
+        EnemyKilled,
+    };
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin, CollisionPlugin, DamagePlugin))
+            .init_asset::<AudioSource>()
+            .init_resource::<CosmeticRng>()
+            .insert_state(GameState::Running)
+            .insert_resource(Score::new())
+            .add_event::<CollisionEvent>()
+            .add_event::<PlayerHit>()
+            .add_event::<EnemyKilled>()
+            .add_systems(FixedUpdate, clean_dead);
+        app
+    }
+
+    fn spawn_enemy(app: &mut App, x: f32) -> Entity {
+        app.world_mut()
+            .spawn((
+                Enemy,
+                Health { hp: 10 },
+                Transform::from_xyz(x, 0., 0.),
+                Collider::new(Vec2::splat(20.)),
+                CollisionLayers::new(CollisionLayer::Enemy),
+            ))
+            .id()
+    }
+
+    fn ability(id: u128) -> Ability {
+        Handle::<AbilityDef>::weak_from_u128(id).id()
+    }
+
+    fn tick(app: &mut App) {
+        app.world_mut().run_schedule(FixedUpdate);
+    }
+
+    #[test]
+    fn one_swing_hits_each_enemy_once() {
+        let mut app = test_app();
+        let first = spawn_enemy(&mut app, 0.);
+        let second = spawn_enemy(&mut app, 30.);
+        app.world_mut().spawn((
+            Transform::default(),
+            Collider::new(Vec2::splat(100.)),
+            CollisionLayers::new(CollisionLayer::PlayerHitbox),
+            DamageOnHit {
+                amount: 3,
+                kind: ability(1),
+                knockback: 0.,
+                stun: 0.,
+            },
+            HitRegistry::default(),
+        ));
+
+        for _ in 0..5 {
+            tick(&mut app);
+        }
+
+        for enemy in [first, second] {
+            assert_eq!(app.world().get::<Health>(enemy).unwrap().hp, 7);
+            assert_eq!(app.world().get::<LastHitBy>(enemy).unwrap().0, ability(1));
+        }
+        assert_eq!(app.world().resource::<Score>().get_damage_dealt(), 6);
+        assert_eq!(damage_numbers(&mut app), 2);
+    }
+
+    fn damage_numbers(app: &mut App) -> usize {
+        let mut numbers = app.world_mut().query::<&DamageNumber>();
+        numbers.iter(app.world()).count()
+    }
+
+    #[test]
+    fn only_damage_that_lands_is_counted() {
+        let mut app = test_app();
+        let enemy = app.world_mut().spawn((Enemy, Health { hp: 1 }, Transform::default())).id();
+        // Still in the air, so every hit on it is ignored
+        let bigfoot = app
+            .world_mut()
+            .spawn((Bigfoot::new(0., 0., Handle::default(), Handle::default()), Transform::default()))
+            .id();
+
+        for target in [enemy, bigfoot] {
+            app.world_mut().send_event(DamageEvent {
+                source: Entity::PLACEHOLDER,
+                target,
+                amount: 5,
+                kind: ability(1),
+            });
+        }
+        tick(&mut app);
+
+        assert_eq!(app.world().resource::<Score>().get_damage_dealt(), 1);
+        assert_eq!(damage_numbers(&mut app), 1);
+    }
+
+    #[test]
+    fn hitboxes_without_a_registry_hit_every_tick() {
+        let mut app = test_app();
+        let enemy = spawn_enemy(&mut app, 0.);
+        app.world_mut().spawn((
+            Transform::default(),
+            Collider::new(Vec2::splat(100.)),
+            CollisionLayers::new(CollisionLayer::PlayerHitbox),
+            DamageOnHit {
+                amount: 1,
+                kind: ability(2),
+                knockback: 0.,
+                stun: 0.,
+            },
+        ));
+
+        for _ in 0..4 {
+            tick(&mut app);
+        }
+
+        assert_eq!(app.world().get::<Health>(enemy).unwrap().hp, 6);
+    }
+
+    fn spawn_toucher(app: &mut App, x: f32, damage: i32, knockback: f32) {
+        app.world_mut().spawn((
+            Enemy,
+            Transform::from_xyz(x, 0., 0.),
+            Collider::new(Vec2::splat(20.)),
+            CollisionLayers::new(CollisionLayer::Enemy),
+            ContactDamage { damage, knockback },
+        ));
+    }
+
+    fn player_hits(app: &mut App) -> Vec<PlayerHit> {
+        app.world_mut().resource_mut::<Events<PlayerHit>>().drain().collect()
+    }
+
+    #[test]
+    fn touching_enemies_land_one_hit_with_i_frames() {
+        let mut app = test_app();
+        app.world_mut().spawn((
+            Player,
+            Transform::default(),
+            Collider::new(Vec2::splat(20.)),
+            CollisionLayers::new(CollisionLayer::Player),
+        ));
+        spawn_toucher(&mut app, -10., 10, 60.);
+        spawn_toucher(&mut app, -15., 25, 120.);
+
+        tick(&mut app);
+
+        let hits = player_hits(&mut app);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].amount, 25);
+        assert_eq!(hits[0].knockback, Vec2::new(120., 0.));
+        assert_eq!(hits[0].invulnerability, CONTACT_INVULNERABILITY);
+    }
+
+    #[test]
+    fn invulnerable_players_take_no_contact_damage() {
+        let mut app = test_app();
+        app.world_mut().spawn((
+            Player,
+            Transform::default(),
+            Collider::new(Vec2::splat(20.)),
+            CollisionLayers::new(CollisionLayer::Player),
+            Invulnerability {
+                timer: Timer::from_seconds(1.0, TimerMode::Once),
+            },
+        ));
+        spawn_toucher(&mut app, 5., 10, 60.);
+
+        tick(&mut app);
+
+        assert!(player_hits(&mut app).is_empty());
+    }
+
+    #[test]
+    fn bursts_push_enemies_outward_and_stun_them() {
+        let mut app = test_app();
+        let enemy = spawn_enemy(&mut app, 30.);
+        app.world_mut().spawn((
+            Transform::default(),
+            Collider::from_shape(ColliderShape::Circle { radius: 50. }),
+            CollisionLayers::new(CollisionLayer::PlayerHitbox),
+            DamageOnHit {
+                amount: 1,
+                kind: ability(2),
+                knockback: 500.,
+                stun: 0.6,
+            },
+            HitRegistry::default(),
+        ));
+
+        tick(&mut app);
+
+        assert_eq!(app.world().get::<Knockback>(enemy).unwrap().velocity, Vec2::new(500., 0.));
+        assert!(app.world().get::<Stunned>(enemy).is_some());
+    }
+
+    #[test]
+    fn lines_push_along_their_facing() {
+        let source = Transform::from_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        let shape = ColliderShape::Obb {
+            half_size: Vec2::new(100., 10.),
+        };
+        let direction = knockback_direction(&source, shape, &Transform::from_xyz(50., 50., 0.));
+        assert!(direction.abs_diff_eq(Vec2::Y, 1e-5));
+    }
+}
@@ -4,82 +4,504 @@ use bevy::prelude::*;
 use rand::Rng;
 
 use crate::{
-    components::{Collider, Enemy, GameState, Health, Player, Resettable, Velocity},
-    EnemySpawnRate, GameTextures, ENEMY_SPEED, PLAYER_RADIUS, SPRITE_SCALE, SPRITE_SIZE,
+    clean_dead,
+    collision::detect_collisions,
+    components::{
+        Collider, ColliderShape, CollisionLayer, CollisionLayers, ContactDamage, Enemy, EnemyKind, EnemyProjectile, GameState, Health,
+        Invulnerability, Knockback, Lifetime, MovementSpeed, Player, Projectile, RangedAttacker, Resettable, Steering,
+        SteeringBehaviour, Stunned, Velocity,
+    },
+    rng::GameRng,
+    steering::player_tracking_system,
+    waves::{WaveDirector, WaveScript},
+    CollisionEvent, EnemyKilled, GameTextures, PlayerHit, ENEMY_SPEED, SPRITE_SCALE, SPRITE_SIZE,
 };
 
+// Spitter projectile constants
+const SPIT_SPEED: f32 = 300.;
+const SPIT_DAMAGE: i32 = 15;
+const SPIT_SIZE: f32 = 20.;
+
+// Distance a regular oni shoves the player on touch
+const CONTACT_KNOCKBACK: f32 = 60.;
+
+// Knockback bleeds off at this rate per second and stops once it is slower than the minimum
+const KNOCKBACK_FRICTION: f32 = 8.;
+const KNOCKBACK_MIN_SPEED: f32 = 5.;
+
+// Distance from the parent that splitter children spawn at
+const SPLIT_SPREAD: f32 = 30.;
+
+pub struct EnemyArchetype {
+    pub hp: i32,
+    pub speed: f32,
+    pub size: f32, // Multiplier on the oni sprite and collider
+    pub contact_damage: i32,
+    pub contact_knockback: f32,
+    pub score: u32,
+    pub color: Color,
+    pub ranged: Option<(f32, f32)>, // Preferred range and seconds between shots
+    pub split: Option<(EnemyKind, usize)>, // Children spawned on death
+    pub steering: Steering,
+}
+
+// Default crowd weights, archetypes only differ in how they approach the player
+const fn steering(behaviour: SteeringBehaviour) -> Steering {
+    Steering {
+        seek: 1.0,
+        separation: 1.5,
+        cohesion: 0.1,
+        behaviour,
+        bias: 0.,
+    }
+}
+
+pub fn archetype(kind: EnemyKind) -> EnemyArchetype {
+    match kind {
+        EnemyKind::Oni => EnemyArchetype {
+            hp: 1,
+            speed: ENEMY_SPEED,
+            size: 1.0,
+            contact_damage: 10,
+            contact_knockback: CONTACT_KNOCKBACK,
+            score: 1,
+            color: Color::WHITE,
+            ranged: None,
+            split: None,
+            steering: steering(SteeringBehaviour::Seek),
+        },
+        EnemyKind::Runner => EnemyArchetype {
+            hp: 1,
+            speed: ENEMY_SPEED * 1.75,
+            size: 0.7,
+            contact_damage: 5,
+            contact_knockback: CONTACT_KNOCKBACK * 0.5,
+            score: 1,
+            color: Color::srgb(1.0, 0.9, 0.4),
+            ranged: None,
+            split: None,
+            steering: steering(SteeringBehaviour::Flank { angle: 0.8 }),
+        },
+        EnemyKind::Brute => EnemyArchetype {
+            hp: 6,
+            speed: ENEMY_SPEED * 0.6,
+            size: 1.8,
+            contact_damage: 25,
+            contact_knockback: CONTACT_KNOCKBACK * 2.0,
+            score: 5,
+            color: Color::srgb(1.0, 0.4, 0.4),
+            ranged: None,
+            split: None,
+            steering: steering(SteeringBehaviour::Seek),
+        },
+        EnemyKind::Spitter => EnemyArchetype {
+            hp: 2,
+            speed: ENEMY_SPEED * 0.8,
+            size: 1.0,
+            contact_damage: 5,
+            contact_knockback: CONTACT_KNOCKBACK,
+            score: 3,
+            color: Color::srgb(0.5, 1.0, 0.5),
+            ranged: Some((350., 2.0)),
+            split: None,
+            steering: steering(SteeringBehaviour::Orbit { radius: 350. }),
+        },
+        EnemyKind::Splitter => EnemyArchetype {
+            hp: 3,
+            speed: ENEMY_SPEED * 0.85,
+            size: 1.3,
+            contact_damage: 10,
+            contact_knockback: CONTACT_KNOCKBACK,
+            score: 3,
+            color: Color::srgb(0.8, 0.5, 1.0),
+            ranged: None,
+            split: Some((EnemyKind::Splitling, 3)),
+            steering: steering(SteeringBehaviour::Seek),
+        },
+        EnemyKind::Splitling => EnemyArchetype {
+            hp: 1,
+            speed: ENEMY_SPEED * 1.3,
+            size: 0.6,
+            contact_damage: 5,
+            contact_knockback: CONTACT_KNOCKBACK * 0.5,
+            score: 1,
+            color: Color::srgb(0.9, 0.7, 1.0),
+            ranged: None,
+            split: None,
+            steering: steering(SteeringBehaviour::Flank { angle: 0.5 }),
+        },
+    }
+}
+
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
    fn build(&self, app: &mut App) {
-       app.add_systems(Update, enemy_spawn_system.run_if(in_state(GameState::Running)))
-           .add_systems(FixedUpdate, (player_tracking_system, enemy_movement_system).run_if(in_state(GameState::Running)));
+       app.insert_resource(WaveDirector::new(WaveScript::load()))
+           .add_systems(OnEnter(GameState::Reset), restart_waves)
+           .add_systems(
+               FixedUpdate,
+               (
+                   enemy_spawn_system,
+                   enemy_movement_system.after(player_tracking_system),
+                   knockback_system,
+                   update_stunned,
+                   spitter_attack_system,
+                   enemy_projectile_hits.after(detect_collisions),
+                   split_on_death.after(clean_dead),
+               )
+                   .run_if(in_state(GameState::Running)),
+           );
    } 
 }
 
+fn restart_waves(mut director: ResMut<WaveDirector>) {
+    director.restart();
+}
+
 fn enemy_spawn_system(
     mut commands: Commands,
     game_textures: Res<GameTextures>,
-    mut enemy_spawn_rate: ResMut<EnemySpawnRate>,
-    player_query: Query<&Transform, With<Player>>
+    time: Res<Time>,
+    mut director: ResMut<WaveDirector>,
+    player_query: Query<&Transform, With<Player>>,
+    enemy_query: Query<(), With<Enemy>>,
+    mut rng: ResMut<GameRng>,
 ) {
+    let orders = director.tick(time.delta(), enemy_query.iter().count(), &mut *rng);
+
     if let Ok(player_transform) = player_query.get_single() {
-        let player_position = player_transform.translation;
+        let player_position = player_transform.translation.truncate();
+
+        for order in orders {
+            spawn_enemy(&mut commands, &game_textures, order.kind, player_position + order.offset, &mut rng);
+        }
+    }
+}
+
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    game_textures: &GameTextures,
+    kind: EnemyKind,
+    position: Vec2,
+    rng: &mut GameRng,
+) {
+    let stats = archetype(kind);
+    let scale = SPRITE_SCALE / 8.0 * stats.size;
+
+    let mut enemy = commands.spawn((
+            SpriteBundle {
+                texture: game_textures.enemy.clone(),
+                sprite: Sprite {
+                    color: stats.color,
+                    ..Default::default()
+                },
+                transform: Transform {
+                    translation: position.extend(10.),
+                    scale: Vec3::new(scale, scale, 0.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Health {
+                hp: stats.hp,
+            },
+            Collider::from_shape(ColliderShape::Circle {
+                radius: SPRITE_SIZE.0 * SPRITE_SCALE * stats.size / 2.,
+            }),
+            CollisionLayers::new(CollisionLayer::Enemy),
+            Enemy,
+            kind,
+            MovementSpeed(stats.speed),
+            ContactDamage {
+                damage: stats.contact_damage,
+                knockback: stats.contact_knockback,
+            },
+            // Entity indices differ from one run to the next, so anything per enemy comes from the seed
+            Steering {
+                bias: rng.gen_range(-PI..PI),
+                ..stats.steering
+            },
+            Velocity {
+                x: 0.,
+                y: 0.,
+            },
+            Resettable,
+    ));
+
+    if let Some((range, cooldown)) = stats.ranged {
+        enemy.insert(RangedAttacker {
+            timer: Timer::from_seconds(cooldown, TimerMode::Once),
+            range,
+        });
+    }
+}
+
+fn enemy_movement_system(
+    mut query: Query<(&Velocity, &MovementSpeed, &mut Transform), (With<Enemy>, Without<Stunned>)>,
+    time: Res<Time>,
+) {
+    for (velocity, speed, mut transform) in query.iter_mut() {
+        let translation = &mut transform.translation;
+
+        translation.x += velocity.x * time.delta_seconds() * speed.0;
+        translation.y += velocity.y * time.delta_seconds() * speed.0;
+    }
+}
+
+fn knockback_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Knockback, &mut Transform)>,
+) {
+    // Exponential friction, so the push fades the same way at any tick rate
+    let friction = (-KNOCKBACK_FRICTION * time.delta_seconds()).exp();
+
+    for (entity, mut knockback, mut transform) in query.iter_mut() {
+        transform.translation += (knockback.velocity * time.delta_seconds()).extend(0.);
+        knockback.velocity *= friction;
+
+        if knockback.velocity.length() < KNOCKBACK_MIN_SPEED {
+            commands.entity(entity).remove::<Knockback>();
+        }
+    }
+}
+
+fn update_stunned(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Stunned)>,
+) {
+    for (entity, mut stunned) in query.iter_mut() {
+        if stunned.timer.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Stunned>();
+        }
+    }
+}
+
+fn spitter_attack_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_textures: Res<GameTextures>,
+    player_query: Query<&Transform, With<Player>>,
+    mut spitter_query: Query<(&Transform, &mut RangedAttacker), (With<Enemy>, Without<Stunned>)>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
 
-        let mut rng = rand::thread_rng();
-        let angle = rng.gen_range(0.0..(2.0 * PI));
+    for (transform, mut attacker) in spitter_query.iter_mut() {
+        attacker.timer.tick(time.delta());
 
-        let x = player_position.x + PLAYER_RADIUS * angle.cos();
-        let y = player_position.y + PLAYER_RADIUS * angle.sin();
+        let to_player = (player_transform.translation - transform.translation).truncate();
+        if !attacker.timer.finished() || to_player.length() > attacker.range * 1.2 {
+            continue;
+        }
 
+        let direction = to_player.normalize_or_zero();
         commands.spawn((
                 SpriteBundle {
-                    texture: game_textures.enemy.clone(),
+                    texture: game_textures.line.clone(),
                     transform: Transform {
-                        translation: Vec3::new(x, y, 10.),
-                        scale: Vec3::new(SPRITE_SCALE/8.0, SPRITE_SCALE/8.0, 0.),
+                        translation: transform.translation.truncate().extend(9.),
+                        scale: Vec3::new(SPIT_SIZE, SPIT_SIZE, 0.),
                         ..Default::default()
                     },
                     ..Default::default()
                 },
-                Health {
-                    hp: 1,
+                EnemyProjectile {
+                    damage: SPIT_DAMAGE,
                 },
-                Collider::new(Vec2::splat(SPRITE_SIZE.0 * SPRITE_SCALE)),
-                Enemy,
-                Velocity {
-                    x: 0.,
-                    y: 0.,
+                Collider::from_shape(ColliderShape::Circle {
+                    radius: SPIT_SIZE / 2.,
+                }),
+                CollisionLayers::new(CollisionLayer::EnemyProjectile),
+                Projectile {
+                    direction,
+                    speed: SPIT_SPEED,
+                },
+                Lifetime {
+                    timer: Timer::from_seconds(attacker.range * 2.0 / SPIT_SPEED, TimerMode::Once),
                 },
                 Resettable,
         ));
-        enemy_spawn_rate.0 -= 0.025;
+        attacker.timer.reset();
     }
 }
 
-
-fn player_tracking_system(
-    player_query: Query<&Transform, With<Player>>,
-    mut enemy_query: Query<(&mut Velocity, &Transform),With<Enemy>>,
-) { 
-    if let Ok(player_transform) = player_query.get_single() {
-        for (mut velocity, enemy_transform) in enemy_query.iter_mut() {
-            let direction_vector = (player_transform.translation - enemy_transform.translation).normalize();
-            velocity.x = direction_vector.x;
-            velocity.y = direction_vector.y;
+fn enemy_projectile_hits(
+    mut commands: Commands,
+    mut collision_reader: EventReader<CollisionEvent>,
+    projectile_query: Query<&EnemyProjectile>,
+    invulnerable_query: Query<(), With<Invulnerability>>,
+    mut player_hits: EventWriter<PlayerHit>,
+) {
+    for event in collision_reader.read() {
+        // Spit flies straight through a player with i-frames
+        if event.other_layer != CollisionLayer::Player || invulnerable_query.contains(event.other) {
+            continue;
         }
 
+        if let Ok(projectile) = projectile_query.get(event.entity) {
+            player_hits.send(PlayerHit {
+                amount: projectile.damage,
+                knockback: Vec2::ZERO,
+                invulnerability: 0.0,
+            });
+            commands.entity(event.entity).despawn();
+        }
     }
 }
 
-fn enemy_movement_system(
-    mut query: Query<(&Velocity, &mut Transform), With<Enemy>>,
-    time: Res<Time>,
+fn split_on_death(
+    mut commands: Commands,
+    game_textures: Res<GameTextures>,
+    mut kill_reader: EventReader<EnemyKilled>,
+    mut rng: ResMut<GameRng>,
 ) {
-    for (velocity, mut transform) in query.iter_mut() {
-        let translation = &mut transform.translation;
+    for kill in kill_reader.read() {
+        if let Some((child, count)) = archetype(kill.kind).split {
+            for i in 0..count {
+                let offset = Vec2::from_angle(i as f32 * 2.0 * PI / count as f32) * SPLIT_SPREAD;
+                spawn_enemy(&mut commands, &game_textures, child, kill.position + offset, &mut rng);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn spawns_director_orders_around_the_player() {
+        let script = WaveScript::parse("(
+            waves: [
+                (start: 0.0, end: None, spawn_interval: 1.0, ring_radius: 300.0, max_alive: 100, mix: [(Oni, 1.0)], formations: [(Ring(count: 6), 1.0)]),
+            ],
+        )").unwrap();
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(GameTextures {
+                player: Handle::default(),
+                enemy: Handle::default(),
+                line: Handle::default(),
+                map: Handle::default(),
+            })
+            .insert_resource(WaveDirector::new(script))
+            .init_resource::<GameRng>()
+            .add_systems(FixedUpdate, enemy_spawn_system);
+        app.world_mut().spawn((Player, Transform::from_xyz(100., 50., 10.)));
+
+        app.world_mut().resource_mut::<Time>().advance_by(Duration::from_secs(1));
+        app.world_mut().run_schedule(FixedUpdate);
+
+        let mut enemies = app.world_mut().query_filtered::<&Transform, With<Enemy>>();
+        let positions: Vec<Vec2> = enemies.iter(app.world()).map(|t| t.translation.truncate()).collect();
+        assert_eq!(positions.len(), 6);
+        assert!(positions.iter().all(|p| (p.distance(Vec2::new(100., 50.)) - 300.0).abs() < 1e-2));
+    }
+
+    fn textures() -> GameTextures {
+        GameTextures {
+            player: Handle::default(),
+            enemy: Handle::default(),
+            line: Handle::default(),
+            map: Handle::default(),
+        }
+    }
+
+    #[test]
+    fn splitters_spawn_children_on_death() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(textures())
+            .init_resource::<GameRng>()
+            .add_event::<EnemyKilled>()
+            .add_systems(Update, split_on_death);
+
+        app.world_mut().send_event(EnemyKilled {
+            kind: EnemyKind::Splitter,
+            position: Vec2::new(10., 10.),
+            ability: None,
+        });
+        app.world_mut().send_event(EnemyKilled {
+            kind: EnemyKind::Oni,
+            position: Vec2::ZERO,
+            ability: None,
+        });
+        app.update();
+
+        let mut kinds = app.world_mut().query::<&EnemyKind>();
+        let kinds: Vec<EnemyKind> = kinds.iter(app.world()).copied().collect();
+        assert_eq!(kinds, vec![EnemyKind::Splitling; 3]);
+    }
+
+    #[test]
+    fn enemies_move_at_their_archetype_speed() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(textures())
+            .add_systems(Update, enemy_movement_system);
+
+        let mut commands = app.world_mut().commands();
+        spawn_enemy(&mut commands, &textures(), EnemyKind::Runner, Vec2::ZERO, &mut GameRng::default());
+        spawn_enemy(&mut commands, &textures(), EnemyKind::Brute, Vec2::ZERO, &mut GameRng::default());
+        app.world_mut().flush();
+
+        // Heading straight right at full steering strength
+        let mut velocities = app.world_mut().query::<&mut Velocity>();
+        for mut velocity in velocities.iter_mut(app.world_mut()) {
+            velocity.x = 1.;
+        }
+
+        app.world_mut().resource_mut::<Time>().advance_by(std::time::Duration::from_secs(1));
+        app.world_mut().run_schedule(Update);
+
+        let mut enemies = app.world_mut().query::<(&EnemyKind, &Transform)>();
+        for (kind, transform) in enemies.iter(app.world()) {
+            assert!((transform.translation.x - archetype(*kind).speed).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn knockback_fades_and_stunned_enemies_hold_still() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_systems(Update, (enemy_movement_system, knockback_system, update_stunned));
+
+        let enemy = app
+            .world_mut()
+            .spawn((
+                Enemy,
+                Velocity { x: 1., y: 0. },
+                MovementSpeed(100.),
+                Transform::default(),
+                Knockback { velocity: Vec2::new(0., 400.) },
+                Stunned {
+                    timer: Timer::from_seconds(0.5, TimerMode::Once),
+                },
+            ))
+            .id();
+
+        app.world_mut().resource_mut::<Time>().advance_by(std::time::Duration::from_millis(100));
+        app.world_mut().run_schedule(Update);
+
+        let transform = app.world().get::<Transform>(enemy).unwrap();
+        assert_eq!(transform.translation.x, 0.);
+        assert!(transform.translation.y > 0.);
+        assert!(app.world().get::<Knockback>(enemy).unwrap().velocity.y < 400.);
+
+        for _ in 0..10 {
+            app.world_mut().resource_mut::<Time>().advance_by(std::time::Duration::from_millis(100));
+            app.world_mut().run_schedule(Update);
+        }
 
-        translation.x += velocity.x * time.delta_seconds() * ENEMY_SPEED;
-        translation.y += velocity.y * time.delta_seconds() * ENEMY_SPEED;
+        assert!(app.world().get::<Knockback>(enemy).is_none());
+        assert!(app.world().get::<Stunned>(enemy).is_none());
+        assert!(app.world().get::<Transform>(enemy).unwrap().translation.x > 0.);
     }
 }
@@ -1,8 +1,57 @@
 use bevy::prelude::*;
 
+use crate::components::{Ability, CollisionLayer, EnemyKind};
+
+// Sent for every overlapping pair where `entity`'s filter includes `other`'s layer
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CollisionEvent {
+    pub entity: Entity,
+    pub layer: CollisionLayer,
+    pub other: Entity,
+    pub other_layer: CollisionLayer,
+}
+
+// A hitbox dealing damage to a single target
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DamageEvent {
+    pub source: Entity,
+    pub target: Entity,
+    pub amount: i32,
+    pub kind: Ability,
+}
+
+// Damage that actually landed, after i-frames and overkill, sent by apply_damage and damage_bigfoot
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DamageApplied {
+    pub target: Entity,
+    pub amount: i32,
+    pub kind: Ability,
+}
+
+// Sent by clean_dead whenever an enemy is despawned
 #[derive(Event)]
-pub enum CollisionEvent{
-    Collision,
-    Damage(Entity),
-} // Event carrying the entity to delete
+pub struct EnemyKilled {
+    pub kind: EnemyKind,
+    pub position: Vec2,
+    pub ability: Option<Ability>, // Ability that landed the killing blow
+}
+
+// Damage dealt to the player, resolved by apply_player_damage
+#[derive(Event)]
+pub struct PlayerHit {
+    pub amount: i32,
+    pub knockback: Vec2,
+    pub invulnerability: f32, // Seconds of i-frames granted after the hit, 0 for none
+}
+
+// Sent by the beat clock every time the music crosses a beat
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Beat {
+    pub index: u64, // Beats since the track started, the first beat is 0
+}
 
+// The player took an upgrade from the level-up draft
+#[derive(Event, Clone, Copy, Debug)]
+pub struct UpgradeChosen {
+    pub index: usize,
+}
@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+
+use crate::components::{Cooldowns, Health, Player, Score};
+use crate::enemy::archetype;
+use crate::{clean_dead, EnemyKilled, PLAYER_MAX_HEALTH};
+
+// Kill reward constants
+const KILL_HEAL: i32 = 1;
+const KILL_COOLDOWN_REDUCTION: f32 = 0.05;
+
+pub struct KillPlugin;
+
+impl Plugin for KillPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EnemyKilled>()
+            .add_systems(
+                FixedUpdate,
+                (award_score, heal_on_kill, reduce_cooldowns_on_kill).after(clean_dead),
+            );
+    }
+}
+
+fn award_score(
+    mut kill_reader: EventReader<EnemyKilled>,
+    mut score: ResMut<Score>,
+) {
+    for kill in kill_reader.read() {
+        score.increment(archetype(kill.kind).score);
+        match kill.ability {
+            Some(ability) => info!("{:?} killed by {}, score: {} ({} kills)", kill.kind, ability, score.get_points(), score.get_enemies_killed()),
+            None => info!("{:?} killed, score: {} ({} kills)", kill.kind, score.get_points(), score.get_enemies_killed()),
+        }
+    }
+}
+
+fn heal_on_kill(
+    mut kill_reader: EventReader<EnemyKilled>,
+    mut player_query: Query<&mut Health, With<Player>>,
+) {
+    let kills = kill_reader.read().count() as i32;
+
+    if kills == 0 {
+        return;
+    }
+
+    if let Ok(mut health) = player_query.get_single_mut() {
+        health.heal(kills * KILL_HEAL, PLAYER_MAX_HEALTH);
+    }
+}
+
+fn reduce_cooldowns_on_kill(
+    mut kill_reader: EventReader<EnemyKilled>,
+    mut cooldowns_query: Query<&mut Cooldowns, With<Player>>,
+) {
+    let kills = kill_reader.read().count();
+
+    if kills == 0 {
+        return;
+    }
+
+    for mut cooldowns in cooldowns_query.iter_mut() {
+        cooldowns.reduce_all(kills as f32 * KILL_COOLDOWN_REDUCTION);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::state::app::StatesPlugin;
+
+    use super::*;
+    use crate::{
+        abilities::AbilityDef,
+        components::{Ability, Enemy, EnemyKind, GameState, LastHitBy},
+    };
+
+    fn ability(id: u128) -> Ability {
+        Handle::<AbilityDef>::weak_from_u128(id).id()
+    }
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin, KillPlugin))
+            .init_state::<GameState>()
+            .insert_resource(Score::new())
+            .add_systems(FixedUpdate, clean_dead);
+        app
+    }
+
+    fn spawn_player(app: &mut App, hp: i32) -> Entity {
+        let mut cooldowns = Cooldowns::new();
+        cooldowns.configure(ability(4), 5.0, 1);
+
+        app.world_mut()
+            .spawn((Player, Health { hp }, cooldowns))
+            .id()
+    }
+
+    #[test]
+    fn killing_an_enemy_awards_score() {
+        let mut app = test_app();
+        spawn_player(&mut app, 100);
+        app.world_mut().spawn((Enemy, Health { hp: 0 }, LastHitBy(ability(1))));
+        app.world_mut().spawn((Enemy, Health { hp: 1 }));
+
+        app.world_mut().run_schedule(FixedUpdate);
+
+        assert_eq!(app.world().resource::<Score>().get_enemies_killed(), 1);
+    }
+
+    #[test]
+    fn killed_event_carries_the_killing_ability() {
+        let mut app = test_app();
+        app.world_mut().spawn((Enemy, Health { hp: -2 }, LastHitBy(ability(2))));
+
+        app.world_mut().run_schedule(FixedUpdate);
+
+        let events = app.world().resource::<Events<EnemyKilled>>();
+        let kill = events.iter_current_update_events().next().expect("no EnemyKilled event sent");
+        assert_eq!(kill.ability, Some(ability(2)));
+    }
+
+    #[test]
+    fn kills_heal_player_up_to_max() {
+        let mut app = test_app();
+        let player = spawn_player(&mut app, PLAYER_MAX_HEALTH - 1);
+        for _ in 0..3 {
+            app.world_mut().spawn((Enemy, Health { hp: 0 }));
+        }
+
+        app.world_mut().run_schedule(FixedUpdate);
+
+        assert_eq!(app.world().get::<Health>(player).unwrap().hp, PLAYER_MAX_HEALTH);
+    }
+
+    #[test]
+    fn kills_reduce_player_cooldowns() {
+        let mut app = test_app();
+        let player = spawn_player(&mut app, 100);
+        app.world_mut().spawn((Enemy, Health { hp: 0 }, LastHitBy(ability(3))));
+        app.world_mut().spawn((Enemy, Health { hp: 0 }, LastHitBy(ability(3))));
+
+        app.world_mut().run_schedule(FixedUpdate);
+
+        let cooldowns = app.world().get::<Cooldowns>(player).unwrap();
+        let remaining = cooldowns.get_cooldown(ability(4)).unwrap();
+        assert!((remaining - (5.0 - 2.0 * KILL_COOLDOWN_REDUCTION)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn dead_enemies_are_despawned_once() {
+        let mut app = test_app();
+        app.world_mut().spawn((Enemy, Health { hp: 0 }));
+
+        app.world_mut().run_schedule(FixedUpdate);
+        app.world_mut().run_schedule(FixedUpdate);
+
+        assert_eq!(app.world().resource::<Score>().get_enemies_killed(), 1);
+        assert_eq!(app.world_mut().query::<&Enemy>().iter(app.world()).count(), 0);
+    }
+
+    #[test]
+    fn score_uses_the_archetype_value() {
+        let mut app = test_app();
+        app.world_mut().spawn((Enemy, EnemyKind::Brute, Health { hp: 0 }));
+        app.world_mut().spawn((Enemy, EnemyKind::Oni, Health { hp: 0 }));
+
+        app.world_mut().run_schedule(FixedUpdate);
+
+        let score = app.world().resource::<Score>();
+        assert_eq!(score.get_enemies_killed(), 2);
+        assert_eq!(score.get_points(), 6);
+    }
+}
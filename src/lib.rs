@@ -0,0 +1,191 @@
+// Bevy system signatures routinely trip these two lints
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
+pub mod abilities;
+pub mod beat;
+pub mod components;
+pub mod controls;
+pub mod collision;
+pub mod damage;
+pub mod enemy;
+pub mod player;
+pub mod progression;
+pub mod sim;
+pub mod projectile;
+pub mod replay;
+pub mod rng;
+pub mod systems;
+pub mod events;
+pub mod menu;
+pub mod pickups;
+pub mod kill;
+pub mod waves;
+pub mod narrowphase;
+pub mod spatial;
+pub mod steering;
+
+use abilities::AbilitiesPlugin;
+use beat::BeatPlugin;
+use bevy::{ecs::schedule::ExecutorKind, prelude::*};
+use collision::CollisionPlugin;
+use controls::{ControlsFile, ControlsPlugin};
+use damage::DamagePlugin;
+use enemy::EnemyPlugin;
+use kill::KillPlugin;
+use pickups::PickupPlugin;
+use player::PlayerPlugin;
+use progression::ProgressionPlugin;
+use projectile::ProjectilePlugin;
+use replay::{GatherInput, QueuedReplay, Replay, ReplayDir, ReplayPlugin};
+use rng::RngPlugin;
+use events::*;
+use components::{GameState, GameTimer, MapGrid, Score};
+use systems::*;
+use menu::MenuPlugin;
+use steering::SteeringPlugin;
+
+//Assets constants
+const PLAYER_SPRITE: &str = "default_guy.png";
+const ENEMY_SPRITE: &str = "oni.png";
+const LINE_SPRITE: &str = "red_line.png";
+const MAP_SPIRITE: &str = "map.png";
+const SPRITE_SIZE: (f32, f32) = (225., 225.);
+const SPRITE_SCALE: f32 = 0.5;
+
+// Game Cosntants
+const BASE_SPEED: f32 = 250.;
+const PLAYER_RADIUS: f32 = 500.;
+const PLAYER_MAX_HEALTH: i32 = 500;
+
+// Enemy Constants
+const ENEMY_SPEED: f32 = 150.;
+
+// Resources
+#[derive(Resource)]
+pub struct GameTextures {
+    pub player: Handle<Image>,
+    pub enemy: Handle<Image>,
+    pub line: Handle<Image>,
+    pub map: Handle<Image>
+}
+
+// Where abilities aim in world space, following the cursor or the aim stick
+#[derive(Resource)]
+pub struct MouseCoords {
+    pub x: f32,
+    pub y: f32,
+}
+
+// The whole game minus anything that needs a window, so it also runs headless under
+// MinimalPlugins as long as the asset types it loads are registered (see sim::headless_app)
+pub struct GamePlugins;
+
+impl Plugin for GamePlugins {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((AbilitiesPlugin, BeatPlugin, CollisionPlugin, ControlsPlugin, DamagePlugin, PickupPlugin, PlayerPlugin, ProgressionPlugin, ProjectilePlugin, RngPlugin, EnemyPlugin, SteeringPlugin, KillPlugin, MenuPlugin, ReplayPlugin))
+            .insert_resource(Score::new())
+            .insert_resource(MapGrid::default())
+            .insert_resource(GameTimer(0.0))
+            .init_state::<GameState>()
+            .add_systems(Startup, setup)
+            .add_systems(OnEnter(GameState::Reset), reset_game)
+            .add_systems(OnEnter(GameState::Running), ensure_base_map)
+            .add_systems(
+                FixedUpdate,
+                (
+                    clean_dead,
+                    apply_player_damage.run_if(in_state(GameState::Running)),
+                    update_timer.run_if(in_state(GameState::Running)),
+                    update_lifetime.run_if(in_state(GameState::Running)),
+                    update_cooldowns.run_if(in_state(GameState::Running)),
+                    update_cooldowns_ui.run_if(in_state(GameState::Running)),
+                    update_ui_text.run_if(in_state(GameState::Running)),
+                    manage_invulnerability.run_if(in_state(GameState::Running)),
+                    //flicker_system.run_if(in_state(GameState::Running)),
+                    check_and_spawn_map.run_if(in_state(GameState::Running)),
+                    update_bigfoot.run_if(in_state(GameState::Running)),
+                    //update_player_position.run_if(in_state(GameState::Running)),
+                    update_bigfoot_position.run_if(in_state(GameState::Running)),
+                ))
+            .add_event::<CollisionEvent>()
+            .add_event::<PlayerHit>()
+            // Gameplay runs in a fixed order so a seed and an input log always replay the same way
+            .edit_schedule(FixedUpdate, |schedule| {
+                schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+            });
+    }
+}
+
+const USAGE: &str = "usage: gmtk_gamejam [--replay <file> [--verify]]";
+
+// `--replay <file>` plays a saved run back, add `--verify` to check it headless instead
+pub fn run() {
+    let mut replay_path = None;
+    let mut verify = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => match args.next() {
+                Some(path) => replay_path = Some(path),
+                None => {
+                    eprintln!("--replay needs a file to play\n{}", USAGE);
+                    std::process::exit(1);
+                }
+            },
+            "--verify" => verify = true,
+            _ => {
+                eprintln!("Unknown argument {}\n{}", arg, USAGE);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let replay = replay_path.map(|path| match Replay::load(path.as_ref()) {
+        Ok(replay) => replay,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            std::process::exit(1);
+        }
+    });
+
+    if verify {
+        let Some(replay) = replay else {
+            eprintln!("--verify needs a --replay <file> to check");
+            std::process::exit(1);
+        };
+        match replay::verify(&replay) {
+            Ok(outcome) => println!("Replay verified, score {}", outcome.points),
+            Err(error) => {
+                eprintln!("Replay failed to verify: {}", error);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut app = App::new();
+    app
+        // Watching the asset folder lets ability definitions be tweaked while the game runs
+        .add_plugins(DefaultPlugins.set(AssetPlugin {
+            watch_for_changes_override: Some(true),
+            ..Default::default()
+        }))
+        .add_plugins(GamePlugins)
+        // Camera and cursor only mean something with a window to look through
+        .add_systems(FixedUpdate, camera_follow_player.run_if(in_state(GameState::Running)))
+        .add_systems(
+            FixedPreUpdate,
+            update_mouse_position.in_set(GatherInput).run_if(in_state(GameState::Running)),
+        );
+
+    if let Some(file) = ControlsFile::from_user_config() {
+        app.insert_resource(file.load_or_create()).insert_resource(file);
+    }
+    if let Some(dir) = ReplayDir::from_user_data() {
+        app.insert_resource(dir);
+    }
+    if let Some(replay) = replay {
+        app.insert_resource(QueuedReplay(replay));
+    }
+    app.run();
+}
@@ -1,10 +1,20 @@
 use bevy::audio::{AudioBundle, PlaybackMode, PlaybackSettings, Volume};
+use bevy::input::gamepad::GamepadButton;
 use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
 
+use crate::abilities::{AbilityBook, AbilityDef, AbilityModifiers};
+use crate::controls::{Action, ActionInput, Binding, ControlsFile, InputMap};
+use crate::progression::{choose_upgrade, upgrade_label, Experience, UpgradeDraft};
+use crate::replay::{start_replay, GameContent, Replay, ReplayDir, ReplayPlayback};
+use crate::rng::GameRng;
+use crate::UpgradeChosen;
 use crate::components::{
-    Ability, CooldownUi, GameOverUI, GameState, GameTimer, GameTimerText, GameUI, HealthText,
-    MenuUI, PauseMenu, QuitButton, Resettable, RestartButton, Score, ScoreText, StartButton,
-    Wallpaper,
+    BeatIndicator, BindingText, CloseOptionsButton, ConflictText, ControlsButton, ControlsHint, ControlsUI, CooldownUi,
+    GameOverUI, GameState, GameTimer, GameTimerText, GameUI, HealthText, LevelUpUI, MenuUI, OptionsButton, OptionsUI,
+    PauseMenu, RebindButton, Rebinding,
+    ResetControlsButton, SaveControlsButton, UpgradeButton, QuitButton, Resettable, RestartButton, Score, ScoreText,
+    StartButton, VictoryUI, Wallpaper, WatchReplayButton,
 };
 
 pub struct MenuPlugin;
@@ -16,10 +26,24 @@ impl Plugin for MenuPlugin {
             (cleanup_game_ui, setup_menu.after(cleanup_game_ui)),
         )
         .add_systems(OnExit(GameState::Menu), cleanup_menu)
+        .init_resource::<Rebinding>()
         .add_systems(
             Update,
-            (menu_action_system, quit_action_system).run_if(in_state(GameState::Menu)),
+            (
+                menu_action_system,
+                watch_replay_action_system,
+                options_action_system,
+                controls_action_system,
+                close_options_action_system,
+                quit_action_system,
+                // Runs first so the click that starts a capture isn't captured itself
+                (capture_binding, rebind_action_system, reset_controls_action_system, save_controls_action_system)
+                    .chain(),
+                refresh_controls_screen,
+            )
+                .run_if(in_state(GameState::Menu)),
         )
+        .add_systems(Update, update_controls_hints.run_if(resource_changed::<InputMap>))
         .add_systems(
             OnEnter(GameState::Running),
             (
@@ -32,10 +56,24 @@ impl Plugin for MenuPlugin {
             (cleanup_game_ui, setup_game_over_screen.after(cleanup_game_ui)),
         )
         .add_systems(OnExit(GameState::GameOver), kill_game_over_ui)
+        .add_systems(
+            OnEnter(GameState::Won),
+            (cleanup_game_ui, setup_victory_screen.after(cleanup_game_ui)),
+        )
+        .add_systems(OnExit(GameState::Won), kill_victory_ui)
         .add_systems(
             Update,
             (restart_action_system, quit_action_system)
-                .run_if(in_state(GameState::GameOver)),
+                .run_if(in_state(GameState::GameOver).or_else(in_state(GameState::Won))),
+        )
+        .add_systems(OnEnter(GameState::LevelUp), setup_level_up_screen)
+        .add_systems(OnExit(GameState::LevelUp), kill_level_up_ui)
+        .add_systems(
+            Update,
+            // Replays make their own picks
+            level_up_action_system
+                .run_if(in_state(GameState::LevelUp))
+                .run_if(not(resource_exists::<ReplayPlayback>)),
         )
         .add_systems(OnEnter(GameState::Paused), show_pause_menu)
         .add_systems(OnExit(GameState::Paused), hide_pause_menu)
@@ -48,7 +86,7 @@ impl Plugin for MenuPlugin {
     }
 }
 
-fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>, input_map: Res<InputMap>) {
     commands
         .spawn(SpriteBundle {
             texture: asset_server.load("wallpaper.png"),
@@ -90,13 +128,16 @@ fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
                 },
             ));
 
-            parent.spawn(TextBundle::from_section(
-                "WASD to move | Q melee | E ranged | T AoE | F dash",
-                TextStyle {
-                    font: asset_server.load("FiraSans-Bold.ttf"),
-                    font_size: 28.0,
-                    color: Color::WHITE,
-                },
+            parent.spawn((
+                TextBundle::from_section(
+                    input_map.hint(),
+                    TextStyle {
+                        font: asset_server.load("FiraSans-Bold.ttf"),
+                        font_size: 28.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                ControlsHint,
             ));
 
             parent
@@ -133,6 +174,54 @@ fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
                             ));
                         });
 
+                    buttons
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                width: Val::Px(220.0),
+                                height: Val::Px(70.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            background_color: Color::srgba(0.25, 0.55, 0.55, 1.0).into(),
+                            ..Default::default()
+                        })
+                        .insert(WatchReplayButton)
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Watch Replay",
+                                TextStyle {
+                                    font: asset_server.load("FiraSans-Bold.ttf"),
+                                    font_size: 36.0,
+                                    color: Color::WHITE,
+                                },
+                            ));
+                        });
+
+                    buttons
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                width: Val::Px(220.0),
+                                height: Val::Px(70.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            background_color: Color::srgba(0.4, 0.4, 0.4, 1.0).into(),
+                            ..Default::default()
+                        })
+                        .insert(OptionsButton)
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Options",
+                                TextStyle {
+                                    font: asset_server.load("FiraSans-Bold.ttf"),
+                                    font_size: 40.0,
+                                    color: Color::WHITE,
+                                },
+                            ));
+                        });
+
                     buttons
                         .spawn(ButtonBundle {
                             style: Style {
@@ -176,6 +265,9 @@ fn cleanup_menu(
 fn setup_in_game_ui(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    book: Res<AbilityBook>,
+    defs: Res<Assets<AbilityDef>>,
+    input_map: Res<InputMap>,
     existing_ui: Query<Entity, With<GameUI>>,
 ) {
     if !existing_ui.is_empty() {
@@ -185,7 +277,7 @@ fn setup_in_game_ui(
     commands
         .spawn(
             TextBundle::from_section(
-                "WASD to move | Q melee | E ranged | T AoE | F dash",
+                input_map.hint(),
                 TextStyle {
                     font: asset_server.load("FiraSans-Bold.ttf"),
                     font_size: 24.0,
@@ -199,6 +291,7 @@ fn setup_in_game_ui(
                 ..Default::default()
             }),
         )
+        .insert(ControlsHint)
         .insert(GameUI)
         .insert(Resettable);
 
@@ -270,6 +363,25 @@ fn setup_in_game_ui(
                         .insert(Resettable);
                 });
 
+            // Pulses on every beat of the music, lit while a cast would land on-beat
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(16.0),
+                        right: Val::Px(16.0),
+                        width: Val::Px(36.0),
+                        height: Val::Px(36.0),
+                        ..Default::default()
+                    },
+                    border_radius: BorderRadius::MAX,
+                    background_color: Color::srgba(0.9, 0.9, 0.9, 0.4).into(),
+                    ..Default::default()
+                })
+                .insert(BeatIndicator)
+                .insert(GameUI)
+                .insert(Resettable);
+
             parent
                 .spawn(NodeBundle {
                     style: Style {
@@ -286,18 +398,8 @@ fn setup_in_game_ui(
                 .insert(GameUI)
                 .insert(Resettable)
                 .with_children(|parent| {
-                    for ability in [
-                        Ability::Attack,
-                        Ability::Ranged,
-                        Ability::Dash,
-                        Ability::Aoe,
-                    ] {
-                        let label = match ability {
-                            Ability::Attack => "Attack",
-                            Ability::Ranged => "Ranged",
-                            Ability::Dash => "Dash",
-                            Ability::Aoe => "Bladestorm",
-                        };
+                    for handle in book.abilities.iter() {
+                        let label = defs.get(handle).map_or("", |def| def.name.as_str());
 
                         parent
                             .spawn(NodeBundle {
@@ -324,7 +426,7 @@ fn setup_in_game_ui(
                                             color: Color::BLACK,
                                         },
                                     ))
-                                    .insert(CooldownUi)
+                                    .insert(CooldownUi(handle.id()))
                                     .insert(GameUI)
                                     .insert(Resettable);
                             });
@@ -342,6 +444,7 @@ fn cleanup_game_ui(mut commands: Commands, query: Query<Entity, With<GameUI>>) {
 fn setup_pause_menu(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    input_map: Res<InputMap>,
     existing: Query<Entity, With<PauseMenu>>,
 ) {
     if !existing.is_empty() {
@@ -368,7 +471,11 @@ fn setup_pause_menu(
         ))
         .with_children(|parent| {
             parent.spawn(TextBundle::from_section(
-                "Game Paused\nPress Esc to Resume\n\nB to return to Menu",
+                format!(
+                    "Game Paused\nPress {} to Resume\n\n{} to return to Menu",
+                    input_map.label(Action::Pause),
+                    input_map.label(Action::Back)
+                ),
                 TextStyle {
                     font: asset_server.load("FiraSans-Bold.ttf"),
                     font_size: 48.0,
@@ -415,6 +522,447 @@ fn menu_action_system(
     }
 }
 
+// Plays back the most recently saved run
+fn watch_replay_action_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<WatchReplayButton>),
+    >,
+    replay_dir: Option<Res<ReplayDir>>,
+    content: GameContent,
+    mut rng: ResMut<GameRng>,
+    mut next_state: ResMut<NextState<GameState>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                menu_sound(&asset_server, &mut commands);
+                let Some(path) = replay_dir.as_ref().and_then(|dir| dir.latest()) else {
+                    info!("No replays saved yet");
+                    continue;
+                };
+
+                match Replay::load(&path).and_then(|replay| replay.check_content(content.hash())) {
+                    Ok(replay) => start_replay(&mut commands, replay, &mut rng, &mut next_state),
+                    Err(error) => warn!("Could not play {}: {}", path.display(), error),
+                }
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(Color::srgb(0.35, 0.75, 0.75));
+            }
+            Interaction::None => {
+                *color = BackgroundColor(Color::srgb(0.25, 0.55, 0.55));
+            }
+        }
+    }
+}
+
+fn options_action_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<OptionsButton>),
+    >,
+    open_screens: Query<(), With<OptionsUI>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                menu_sound(&asset_server, &mut commands);
+                if open_screens.is_empty() {
+                    setup_options_screen(&mut commands, &asset_server);
+                }
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(Color::srgb(0.55, 0.55, 0.55));
+            }
+            Interaction::None => {
+                *color = BackgroundColor(Color::srgb(0.4, 0.4, 0.4));
+            }
+        }
+    }
+}
+
+// Lists the settings screens, only controls for now
+fn setup_options_screen(commands: &mut Commands, asset_server: &Res<AssetServer>) {
+    let text_style = |size: f32| TextStyle {
+        font: asset_server.load("FiraSans-Bold.ttf"),
+        font_size: size,
+        color: Color::WHITE,
+    };
+    let button = || ButtonBundle {
+        style: Style {
+            width: Val::Px(280.0),
+            height: Val::Px(60.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        background_color: Color::srgb(0.4, 0.4, 0.4).into(),
+        ..Default::default()
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(16.0),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            background_color: Color::srgba(0.0, 0.0, 0.0, 0.9).into(),
+            // Keeps the main menu buttons underneath from being clicked
+            focus_policy: FocusPolicy::Block,
+            ..Default::default()
+        })
+        // Part of the menu, so it goes away with it
+        .insert((OptionsUI, MenuUI))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Options", text_style(56.0)));
+            parent.spawn(button()).insert(ControlsButton).with_children(|parent| {
+                parent.spawn(TextBundle::from_section("Controls", text_style(32.0)));
+            });
+            parent.spawn(button()).insert(CloseOptionsButton).with_children(|parent| {
+                parent.spawn(TextBundle::from_section("Back", text_style(32.0)));
+            });
+        });
+}
+
+fn controls_action_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ControlsButton>),
+    >,
+    open_screens: Query<(), With<ControlsUI>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                menu_sound(&asset_server, &mut commands);
+                if open_screens.is_empty() {
+                    setup_controls_screen(&mut commands, &asset_server);
+                }
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(Color::srgb(0.55, 0.55, 0.55));
+            }
+            Interaction::None => {
+                *color = BackgroundColor(Color::srgb(0.4, 0.4, 0.4));
+            }
+        }
+    }
+}
+
+fn close_options_action_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<CloseOptionsButton>),
+    >,
+    screens: Query<Entity, With<OptionsUI>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                menu_sound(&asset_server, &mut commands);
+                for entity in screens.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(Color::srgb(0.55, 0.55, 0.55));
+            }
+            Interaction::None => {
+                *color = BackgroundColor(Color::srgb(0.4, 0.4, 0.4));
+            }
+        }
+    }
+}
+
+// Every action with what it's bound to, filled in by refresh_controls_screen
+fn setup_controls_screen(commands: &mut Commands, asset_server: &Res<AssetServer>) {
+    let text_style = |size: f32| TextStyle {
+        font: asset_server.load("FiraSans-Bold.ttf"),
+        font_size: size,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.0),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            background_color: Color::srgba(0.0, 0.0, 0.0, 0.9).into(),
+            // Keeps the options buttons underneath from being clicked
+            focus_policy: FocusPolicy::Block,
+            // Above the options screen it was opened from
+            z_index: ZIndex::Global(1),
+            ..Default::default()
+        })
+        // Part of the menu, so it goes away with it
+        .insert((ControlsUI, MenuUI))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Controls", text_style(56.0)));
+
+            for action in Action::ALL {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(16.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with_children(|row| {
+                        row.spawn(
+                            TextBundle::from_section(action.to_string(), text_style(24.0)).with_style(Style {
+                                width: Val::Px(240.0),
+                                ..Default::default()
+                            }),
+                        );
+
+                        row.spawn(ButtonBundle {
+                            style: Style {
+                                width: Val::Px(440.0),
+                                height: Val::Px(36.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            background_color: Color::srgb(0.25, 0.25, 0.75).into(),
+                            ..Default::default()
+                        })
+                        .insert(RebindButton(action))
+                        .with_children(|button| {
+                            button.spawn((TextBundle::from_section("", text_style(22.0)), BindingText(action)));
+                        });
+                    });
+            }
+
+            parent.spawn((
+                TextBundle::from_section("", TextStyle { color: Color::srgb(1.0, 0.4, 0.4), ..text_style(22.0) }),
+                ConflictText,
+            ));
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(16.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|buttons| {
+                    let button = || ButtonBundle {
+                        style: Style {
+                            width: Val::Px(280.0),
+                            height: Val::Px(60.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: Color::srgb(0.4, 0.4, 0.4).into(),
+                        ..Default::default()
+                    };
+
+                    buttons.spawn(button()).insert(ResetControlsButton).with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Reset to defaults", text_style(32.0)));
+                    });
+                    buttons.spawn(button()).insert(SaveControlsButton).with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Save", text_style(32.0)));
+                    });
+                });
+        });
+}
+
+// Binds the action waiting on a capture to the next key, mouse button or gamepad button pressed.
+// Esc cancels, unless it's pause being rebound. Clicks on the screen's own buttons are left to them.
+fn capture_binding(
+    mut rebinding: ResMut<Rebinding>,
+    mut input_map: ResMut<InputMap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    pad_buttons: Res<ButtonInput<GamepadButton>>,
+    ui_buttons: Query<&Interaction, With<Button>>,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+    let clicked_ui = ui_buttons.iter().any(|interaction| *interaction == Interaction::Pressed);
+
+    let binding = keys
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .filter(|_| !clicked_ui)
+                .map(|button| Binding::Mouse(*button))
+        })
+        .or_else(|| pad_buttons.get_just_pressed().next().map(|button| Binding::Pad(button.button_type)));
+    let Some(binding) = binding else {
+        return;
+    };
+
+    if binding != Binding::Key(KeyCode::Escape) || action == Action::Pause {
+        input_map.rebind(action, binding);
+    }
+    rebinding.0 = None;
+}
+
+fn rebind_action_system(
+    mut interaction_query: Query<(&Interaction, &RebindButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut rebinding: ResMut<Rebinding>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for (interaction, RebindButton(action), mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                menu_sound(&asset_server, &mut commands);
+                rebinding.0 = Some(*action);
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(Color::srgb(0.35, 0.75, 0.35));
+            }
+            Interaction::None => {
+                *color = BackgroundColor(Color::srgb(0.25, 0.25, 0.75));
+            }
+        }
+    }
+}
+
+fn reset_controls_action_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ResetControlsButton>),
+    >,
+    mut input_map: ResMut<InputMap>,
+    mut rebinding: ResMut<Rebinding>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                menu_sound(&asset_server, &mut commands);
+                *input_map = InputMap::default();
+                rebinding.0 = None;
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(Color::srgb(0.55, 0.55, 0.55));
+            }
+            Interaction::None => {
+                *color = BackgroundColor(Color::srgb(0.4, 0.4, 0.4));
+            }
+        }
+    }
+}
+
+// Writes the bindings out and closes the screen
+fn save_controls_action_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<SaveControlsButton>),
+    >,
+    input_map: Res<InputMap>,
+    controls_file: Option<Res<ControlsFile>>,
+    mut rebinding: ResMut<Rebinding>,
+    screens: Query<Entity, With<ControlsUI>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                menu_sound(&asset_server, &mut commands);
+                if let Some(file) = controls_file.as_ref() {
+                    match file.save(&input_map) {
+                        Ok(()) => info!("Saved controls to {}", file.0.display()),
+                        Err(error) => warn!("Could not save controls to {}: {}", file.0.display(), error),
+                    }
+                }
+
+                rebinding.0 = None;
+                for entity in screens.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(Color::srgb(0.55, 0.55, 0.55));
+            }
+            Interaction::None => {
+                *color = BackgroundColor(Color::srgb(0.4, 0.4, 0.4));
+            }
+        }
+    }
+}
+
+// Rewrites the binding list and clashes, also covering a screen that was only just spawned
+fn refresh_controls_screen(
+    input_map: Res<InputMap>,
+    rebinding: Res<Rebinding>,
+    new_screens: Query<(), Added<ControlsUI>>,
+    mut binding_texts: Query<(&BindingText, &mut Text), Without<ConflictText>>,
+    mut conflict_texts: Query<&mut Text, With<ConflictText>>,
+) {
+    if !input_map.is_changed() && !rebinding.is_changed() && new_screens.is_empty() {
+        return;
+    }
+    let conflicts = input_map.conflicts();
+
+    for (BindingText(action), mut text) in binding_texts.iter_mut() {
+        let action = *action;
+        let section = &mut text.sections[0];
+        if rebinding.0 == Some(action) {
+            section.value = "Press a key or button...".to_string();
+            section.style.color = Color::srgb(1.0, 0.8, 0.2);
+        } else {
+            let bindings: Vec<String> = input_map.bindings(action).iter().map(Binding::to_string).collect();
+            section.value = if bindings.is_empty() { "-".to_string() } else { bindings.join(", ") };
+            let clashes = conflicts.iter().any(|(_, first, second)| *first == action || *second == action);
+            section.style.color = if clashes { Color::srgb(1.0, 0.4, 0.4) } else { Color::WHITE };
+        }
+    }
+
+    for mut text in conflict_texts.iter_mut() {
+        text.sections[0].value = conflicts
+            .iter()
+            .map(|(binding, first, second)| format!("{} is bound to both {} and {}", binding, first, second))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+}
+
+// Keeps every hint in step with the bindings, including ones changed from the controls screen
+fn update_controls_hints(input_map: Res<InputMap>, mut query: Query<&mut Text, With<ControlsHint>>) {
+    for mut text in query.iter_mut() {
+        text.sections[0].value = input_map.hint();
+    }
+}
+
 fn restart_action_system(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor),
@@ -466,20 +1014,20 @@ fn quit_action_system(
 }
 
 fn handle_escape_pressed(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    input: ActionInput,
     current_state: Res<State<GameState>>,
     mut next_state: ResMut<NextState<GameState>>,
     asset_server: Res<AssetServer>,
     mut commands: Commands,
 ) {
-    if keyboard_input.just_pressed(KeyCode::Escape) {
+    if input.just_pressed(Action::Pause) {
         menu_sound(&asset_server, &mut commands);
         match current_state.get() {
             GameState::Running => next_state.set(GameState::Paused),
             GameState::Paused => next_state.set(GameState::Running),
             _ => {}
         }
-    } else if keyboard_input.just_pressed(KeyCode::KeyB)
+    } else if input.just_pressed(Action::Back)
         && *current_state.get() == GameState::Paused
     {
         menu_sound(&asset_server, &mut commands);
@@ -487,11 +1035,131 @@ fn handle_escape_pressed(
     }
 }
 
+fn setup_level_up_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    experience: Res<Experience>,
+    draft: Res<UpgradeDraft>,
+    book: Res<AbilityBook>,
+    defs: Res<Assets<AbilityDef>>,
+    input_map: Res<InputMap>,
+) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(24.0),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            background_color: Color::srgba(0.0, 0.0, 0.0, 0.7).into(),
+            ..Default::default()
+        })
+        .insert(LevelUpUI)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                format!("Level {}!", experience.level + 1 - experience.pending),
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 72.0,
+                    color: Color::WHITE,
+                },
+            ));
+
+            for (index, upgrade) in draft.options.iter().enumerate() {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(480.0),
+                            height: Val::Px(70.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: Color::srgb(0.25, 0.25, 0.75).into(),
+                        ..Default::default()
+                    })
+                    .insert(UpgradeButton(index))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            format!(
+                                "{}. {}",
+                                Action::UPGRADES
+                                    .get(index)
+                                    .map_or((index + 1).to_string(), |action| input_map.label(*action)),
+                                upgrade_label(upgrade, &book, &defs)
+                            ),
+                            TextStyle {
+                                font: asset_server.load("FiraSans-Bold.ttf"),
+                                font_size: 36.0,
+                                color: Color::WHITE,
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+fn level_up_action_system(
+    mut interaction_query: Query<
+        (&Interaction, &UpgradeButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    input: ActionInput,
+    mut draft: ResMut<UpgradeDraft>,
+    mut experience: ResMut<Experience>,
+    mut modifiers: ResMut<AbilityModifiers>,
+    book: Res<AbilityBook>,
+    defs: Res<Assets<AbilityDef>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut chosen_writer: EventWriter<UpgradeChosen>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    // The upgrade actions pick straight from the draft
+    let mut picked = Action::UPGRADES.iter().position(|action| input.just_pressed(*action));
+
+    for (interaction, UpgradeButton(index), mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => picked = Some(*index),
+            Interaction::Hovered => {
+                *color = BackgroundColor(Color::srgb(0.35, 0.75, 0.35));
+            }
+            Interaction::None => {
+                *color = BackgroundColor(Color::srgb(0.25, 0.25, 0.75));
+            }
+        }
+    }
+
+    let Some(index) = picked else {
+        return;
+    };
+
+    if let Some(upgrade) = choose_upgrade(index, &mut draft, &mut experience, &book, &mut modifiers) {
+        info!("Picked upgrade: {}", upgrade_label(&upgrade, &book, &defs));
+        chosen_writer.send(UpgradeChosen { index });
+        menu_sound(&asset_server, &mut commands);
+        // Back to the game, which drafts again straight away if more levels are pending
+        next_state.set(GameState::Running);
+    }
+}
+
+fn kill_level_up_ui(mut commands: Commands, query: Query<Entity, With<LevelUpUI>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
 fn setup_game_over_screen(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     score: Res<Score>,
     timer: Res<GameTimer>,
+    rng: Res<GameRng>,
 ) {
     commands
         .spawn(NodeBundle {
@@ -519,7 +1187,16 @@ fn setup_game_over_screen(
             ));
 
             parent.spawn(TextBundle::from_section(
-                format!("Final Score: {}", score.get_enemies_killed()),
+                format!("Final Score: {}", score.get_points()),
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 48.0,
+                    color: Color::WHITE,
+                },
+            ));
+
+            parent.spawn(TextBundle::from_section(
+                format!("Damage Dealt: {}", score.get_damage_dealt()),
                 TextStyle {
                     font: asset_server.load("FiraSans-Bold.ttf"),
                     font_size: 48.0,
@@ -536,6 +1213,15 @@ fn setup_game_over_screen(
                 },
             ));
 
+            parent.spawn(TextBundle::from_section(
+                format!("Seed: {}", rng.seed()),
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 32.0,
+                    color: Color::srgb(0.7, 0.7, 0.7),
+                },
+            ));
+
             parent
                 .spawn(NodeBundle {
                     style: Style {
@@ -603,6 +1289,122 @@ fn kill_game_over_ui(mut commands: Commands, query: Query<Entity, With<GameOverU
     }
 }
 
+fn setup_victory_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    score: Res<Score>,
+    timer: Res<GameTimer>,
+) {
+    commands
+        .spawn(ImageBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(24.0),
+                ..Default::default()
+            },
+            image: UiImage::new(asset_server.load("victory.png")),
+            ..Default::default()
+        })
+        .insert(VictoryUI)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "You escaped Gashadokuro!",
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 88.0,
+                    color: Color::WHITE,
+                },
+            ));
+
+            parent.spawn(TextBundle::from_section(
+                format!("Final Score: {}", score.get_points()),
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 48.0,
+                    color: Color::WHITE,
+                },
+            ));
+
+            parent.spawn(TextBundle::from_section(
+                format!("Time: {:.1} seconds", timer.0),
+                TextStyle {
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    font_size: 48.0,
+                    color: Color::WHITE,
+                },
+            ));
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        column_gap: Val::Px(16.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|buttons| {
+                    buttons
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                width: Val::Px(220.0),
+                                height: Val::Px(70.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            background_color: Color::srgba(0.25, 0.75, 0.25, 1.0).into(),
+                            ..Default::default()
+                        })
+                        .insert(RestartButton)
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Play Again",
+                                TextStyle {
+                                    font: asset_server.load("FiraSans-Bold.ttf"),
+                                    font_size: 40.0,
+                                    color: Color::WHITE,
+                                },
+                            ));
+                        });
+
+                    buttons
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                width: Val::Px(220.0),
+                                height: Val::Px(70.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            background_color: Color::srgba(0.75, 0.25, 0.25, 1.0).into(),
+                            ..Default::default()
+                        })
+                        .insert(QuitButton)
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Quit",
+                                TextStyle {
+                                    font: asset_server.load("FiraSans-Bold.ttf"),
+                                    font_size: 40.0,
+                                    color: Color::WHITE,
+                                },
+                            ));
+                        });
+                });
+        });
+}
+
+fn kill_victory_ui(mut commands: Commands, query: Query<Entity, With<VictoryUI>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
 fn menu_sound(asset_server: &Res<AssetServer>, commands: &mut Commands) {
     commands.spawn(AudioBundle {
         source: asset_server.load("sfx/select.ogg"),
@@ -613,3 +1415,59 @@ fn menu_sound(asset_server: &Res<AssetServer>, commands: &mut Commands) {
         },
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn capture(app: &mut App, action: Action, key: KeyCode) {
+        app.world_mut().resource_mut::<Rebinding>().0 = Some(action);
+        let mut keys = ButtonInput::default();
+        keys.press(key);
+        app.insert_resource(keys);
+        app.world_mut().run_system_once(capture_binding);
+    }
+
+    #[test]
+    fn captures_the_next_key_and_esc_cancels() {
+        let mut app = App::new();
+        app.init_resource::<Rebinding>()
+            .init_resource::<InputMap>()
+            .init_resource::<ButtonInput<MouseButton>>()
+            .init_resource::<ButtonInput<GamepadButton>>();
+
+        capture(&mut app, Action::Dash, KeyCode::Space);
+        assert_eq!(app.world().resource::<InputMap>().label(Action::Dash), "Space");
+        assert!(app.world().resource::<Rebinding>().0.is_none());
+
+        capture(&mut app, Action::Dash, KeyCode::Escape);
+        assert_eq!(app.world().resource::<InputMap>().label(Action::Dash), "Space");
+        assert!(app.world().resource::<Rebinding>().0.is_none());
+    }
+
+    #[test]
+    fn clicks_on_the_screens_buttons_are_not_captured() {
+        let mut app = App::new();
+        app.init_resource::<Rebinding>()
+            .init_resource::<InputMap>()
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<ButtonInput<GamepadButton>>();
+        app.world_mut().resource_mut::<Rebinding>().0 = Some(Action::Dash);
+        let save = app.world_mut().spawn((Button, Interaction::Pressed)).id();
+        let mut mouse = ButtonInput::default();
+        mouse.press(MouseButton::Left);
+        app.insert_resource(mouse);
+
+        app.world_mut().run_system_once(capture_binding);
+        assert_eq!(app.world().resource::<Rebinding>().0, Some(Action::Dash));
+        assert!(!app.world().resource::<InputMap>().bindings(Action::Dash).contains(&Binding::Mouse(MouseButton::Left)));
+
+        // A click on the backdrop does bind the mouse button
+        *app.world_mut().get_mut::<Interaction>(save).unwrap() = Interaction::None;
+        app.world_mut().run_system_once(capture_binding);
+        assert!(app.world().resource::<Rebinding>().0.is_none());
+        assert!(app.world().resource::<InputMap>().bindings(Action::Dash).contains(&Binding::Mouse(MouseButton::Left)));
+    }
+}
@@ -0,0 +1,499 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::components::ColliderShape;
+
+// A collider placed in the world, scale is ignored since collider sizes are given in world units
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorldShape {
+    Box { center: Vec2, half_size: Vec2, rotation: Vec2 }, // Rotation as a unit (cos, sin), Vec2::X when axis aligned
+    Circle { center: Vec2, radius: f32 },
+    Capsule { start: Vec2, end: Vec2, radius: f32 },
+    Sector(Sector),
+}
+
+// Pie slice around `direction`, a half angle of PI or more is a full circle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sector {
+    pub center: Vec2,
+    pub radius: f32,
+    pub direction: Vec2,
+    pub half_angle: f32,
+}
+
+impl WorldShape {
+    pub fn from_collider(shape: ColliderShape, transform: &Transform) -> Self {
+        let center = transform.translation.truncate();
+        let rotation = (transform.rotation * Vec3::X).truncate().normalize_or(Vec2::X);
+
+        match shape {
+            ColliderShape::Aabb { half_size } => WorldShape::Box {
+                center,
+                half_size,
+                rotation: Vec2::X,
+            },
+            ColliderShape::Obb { half_size } => WorldShape::Box {
+                center,
+                half_size,
+                rotation,
+            },
+            ColliderShape::Circle { radius } => WorldShape::Circle { center, radius },
+            ColliderShape::Capsule { half_length, radius } => WorldShape::Capsule {
+                start: center - rotation * half_length,
+                end: center + rotation * half_length,
+                radius,
+            },
+            ColliderShape::Sector { radius, half_angle } => WorldShape::Sector(Sector {
+                center,
+                radius,
+                direction: rotation,
+                half_angle,
+            }),
+        }
+    }
+
+    // Axis aligned bounds, used by the broadphase
+    pub fn bounds(&self) -> Rect {
+        match *self {
+            WorldShape::Box { center, half_size, rotation } => {
+                let extents = Vec2::new(
+                    rotation.x.abs() * half_size.x + rotation.y.abs() * half_size.y,
+                    rotation.y.abs() * half_size.x + rotation.x.abs() * half_size.y,
+                );
+                Rect::from_center_half_size(center, extents)
+            }
+            WorldShape::Circle { center, radius } => Rect::from_center_half_size(center, Vec2::splat(radius)),
+            WorldShape::Capsule { start, end, radius } => {
+                let bounds = Rect::from_corners(start, end);
+                Rect::from_corners(bounds.min - Vec2::splat(radius), bounds.max + Vec2::splat(radius))
+            }
+            WorldShape::Sector(sector) => Rect::from_center_half_size(sector.center, Vec2::splat(sector.radius)),
+        }
+    }
+
+    pub fn intersects(&self, other: &WorldShape) -> bool {
+        use WorldShape::*;
+
+        match (*self, *other) {
+            (Box { center: ca, half_size: ha, rotation: ra }, Box { center: cb, half_size: hb, rotation: rb }) => {
+                boxes_overlap(ca, ha, ra, cb, hb, rb)
+            }
+            (Box { center, half_size, rotation }, Circle { center: point, radius })
+            | (Circle { center: point, radius }, Box { center, half_size, rotation }) => {
+                let local = to_local(point, center, rotation);
+                local.distance(local.clamp(-half_size, half_size)) <= radius
+            }
+            (Box { center, half_size, rotation }, Capsule { start, end, radius })
+            | (Capsule { start, end, radius }, Box { center, half_size, rotation }) => {
+                let start = to_local(start, center, rotation);
+                let end = to_local(end, center, rotation);
+                segment_box_distance(start, end, half_size) <= radius
+            }
+            (Circle { center: a, radius: ra }, Circle { center: b, radius: rb }) => a.distance(b) <= ra + rb,
+            (Circle { center, radius: rc }, Capsule { start, end, radius })
+            | (Capsule { start, end, radius }, Circle { center, radius: rc }) => {
+                point_segment_distance(center, start, end) <= rc + radius
+            }
+            (Capsule { start: a0, end: a1, radius: ra }, Capsule { start: b0, end: b1, radius: rb }) => {
+                segment_segment_distance(a0, a1, b0, b1) <= ra + rb
+            }
+            (Sector(sector), Circle { center, radius }) | (Circle { center, radius }, Sector(sector)) => {
+                sector.distance_to_point(center) <= radius
+            }
+            (Sector(sector), Capsule { start, end, radius }) | (Capsule { start, end, radius }, Sector(sector)) => {
+                sector.distance_to_segment(start, end) <= radius
+            }
+            (Sector(sector), Box { center, half_size, rotation }) | (Box { center, half_size, rotation }, Sector(sector)) => {
+                // Either the boundaries cross or one shape holds an edge of the other
+                sector.edges().into_iter().any(|(start, end)| {
+                    segment_hits_box(to_local(start, center, rotation), to_local(end, center, rotation), half_size)
+                }) || box_edges(center, half_size, rotation)
+                    .into_iter()
+                    .any(|(start, end)| sector.distance_to_segment(start, end) <= 0.0)
+            }
+            (Sector(a), Sector(b)) => {
+                a.edges().into_iter().any(|(start, end)| b.distance_to_segment(start, end) <= 0.0)
+                    || b.edges().into_iter().any(|(start, end)| a.distance_to_segment(start, end) <= 0.0)
+                    || a.arc_crosses_arc(&b)
+            }
+        }
+    }
+}
+
+// Point in the box's own frame, where the box is axis aligned around the origin
+fn to_local(point: Vec2, center: Vec2, rotation: Vec2) -> Vec2 {
+    Vec2::new(rotation.x, -rotation.y).rotate(point - center)
+}
+
+// Separating axis test over the two face normals of each box
+fn boxes_overlap(ca: Vec2, ha: Vec2, ra: Vec2, cb: Vec2, hb: Vec2, rb: Vec2) -> bool {
+    let offset = cb - ca;
+    let projected_radius = |half_size: Vec2, rotation: Vec2, axis: Vec2| {
+        half_size.x * rotation.dot(axis).abs() + half_size.y * rotation.perp().dot(axis).abs()
+    };
+
+    [ra, ra.perp(), rb, rb.perp()].into_iter().all(|axis| {
+        offset.dot(axis).abs() <= projected_radius(ha, ra, axis) + projected_radius(hb, rb, axis)
+    })
+}
+
+impl Sector {
+    fn covers_angle(&self, offset: Vec2) -> bool {
+        self.half_angle >= PI || offset == Vec2::ZERO || self.direction.angle_between(offset).abs() <= self.half_angle
+    }
+
+    fn contains(&self, point: Vec2) -> bool {
+        let offset = point - self.center;
+        offset.length() <= self.radius && self.covers_angle(offset)
+    }
+
+    // The two straight sides running from the center out to the arc
+    fn edges(&self) -> [(Vec2, Vec2); 2] {
+        let half_angle = self.half_angle.min(PI);
+        let side = |angle: f32| self.center + Vec2::from_angle(angle).rotate(self.direction) * self.radius;
+
+        [(self.center, side(half_angle)), (self.center, side(-half_angle))]
+    }
+
+    fn distance_to_point(&self, point: Vec2) -> f32 {
+        let offset = point - self.center;
+
+        if self.covers_angle(offset) {
+            (offset.length() - self.radius).max(0.0)
+        } else {
+            self.edges()
+                .into_iter()
+                .map(|(start, end)| point_segment_distance(point, start, end))
+                .fold(f32::INFINITY, f32::min)
+        }
+    }
+
+    fn arc_crosses_segment(&self, start: Vec2, end: Vec2) -> bool {
+        // Solve |start + t * direction - center| = radius for t in [0, 1]
+        let direction = end - start;
+        let offset = start - self.center;
+        let a = direction.length_squared();
+        let b = 2.0 * offset.dot(direction);
+        let c = offset.length_squared() - self.radius * self.radius;
+        let discriminant = b * b - 4.0 * a * c;
+
+        if a == 0.0 || discriminant < 0.0 {
+            return false;
+        }
+
+        let root = discriminant.sqrt();
+        [(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+            .into_iter()
+            .filter(|t| (0.0..=1.0).contains(t))
+            .any(|t| self.covers_angle(offset + direction * t))
+    }
+
+    fn arc_crosses_arc(&self, other: &Sector) -> bool {
+        let between = other.center - self.center;
+        let distance = between.length();
+
+        if distance == 0.0 || distance > self.radius + other.radius || distance < (self.radius - other.radius).abs() {
+            return false;
+        }
+
+        // Intersection points of the two circles
+        let along = (distance * distance + self.radius * self.radius - other.radius * other.radius) / (2.0 * distance);
+        let across = (self.radius * self.radius - along * along).max(0.0).sqrt();
+        let axis = between / distance;
+        let midpoint = self.center + axis * along;
+
+        [midpoint + axis.perp() * across, midpoint - axis.perp() * across]
+            .into_iter()
+            .any(|point| self.covers_angle(point - self.center) && other.covers_angle(point - other.center))
+    }
+
+    fn distance_to_segment(&self, start: Vec2, end: Vec2) -> f32 {
+        let edges = self.edges();
+
+        if self.contains(start)
+            || self.contains(end)
+            || edges.iter().any(|(a, b)| segments_cross(start, end, *a, *b))
+            || self.arc_crosses_segment(start, end)
+        {
+            return 0.0;
+        }
+
+        // Closest approach to the arc happens where the segment passes nearest the circle's center
+        let segment = end - start;
+        let t = if segment == Vec2::ZERO {
+            0.0
+        } else {
+            ((self.center - start).dot(segment) / segment.length_squared()).clamp(0.0, 1.0)
+        };
+        let nearest = start + segment * t;
+        let arc_distance = if self.covers_angle(nearest - self.center) {
+            (nearest.distance(self.center) - self.radius).abs()
+        } else {
+            f32::INFINITY
+        };
+
+        edges
+            .into_iter()
+            .map(|(a, b)| segment_segment_distance(start, end, a, b))
+            .fold(arc_distance, f32::min)
+            .min(self.distance_to_point(start))
+            .min(self.distance_to_point(end))
+    }
+}
+
+fn box_edges(center: Vec2, half_size: Vec2, rotation: Vec2) -> [(Vec2, Vec2); 4] {
+    let corner = |x: f32, y: f32| center + rotation.rotate(Vec2::new(x * half_size.x, y * half_size.y));
+    let corners = [corner(-1., -1.), corner(1., -1.), corner(1., 1.), corner(-1., 1.)];
+
+    [
+        (corners[0], corners[1]),
+        (corners[1], corners[2]),
+        (corners[2], corners[3]),
+        (corners[3], corners[0]),
+    ]
+}
+
+fn point_segment_distance(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let segment = end - start;
+    let length_squared = segment.length_squared();
+    if length_squared == 0.0 {
+        return point.distance(start);
+    }
+
+    let t = ((point - start).dot(segment) / length_squared).clamp(0.0, 1.0);
+    point.distance(start + segment * t)
+}
+
+fn segments_cross(a0: Vec2, a1: Vec2, b0: Vec2, b1: Vec2) -> bool {
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+
+    let (d1, d2) = (side(b0, b1, a0), side(b0, b1, a1));
+    let (d3, d4) = (side(a0, a1, b0), side(a0, a1, b1));
+
+    // Touching and collinear cases are picked up by the endpoint distances instead
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+fn segment_segment_distance(a0: Vec2, a1: Vec2, b0: Vec2, b1: Vec2) -> f32 {
+    if segments_cross(a0, a1, b0, b1) {
+        return 0.0;
+    }
+
+    point_segment_distance(a0, b0, b1)
+        .min(point_segment_distance(a1, b0, b1))
+        .min(point_segment_distance(b0, a0, a1))
+        .min(point_segment_distance(b1, a0, a1))
+}
+
+// Distance from a segment to an axis aligned box centred on the origin
+fn segment_box_distance(start: Vec2, end: Vec2, half_size: Vec2) -> f32 {
+    if segment_hits_box(start, end, half_size) {
+        return 0.0;
+    }
+
+    let corners = [
+        Vec2::new(-half_size.x, -half_size.y),
+        Vec2::new(half_size.x, -half_size.y),
+        Vec2::new(half_size.x, half_size.y),
+        Vec2::new(-half_size.x, half_size.y),
+    ];
+    let point_box_distance = |point: Vec2| point.distance(point.clamp(-half_size, half_size));
+
+    corners
+        .into_iter()
+        .map(|corner| point_segment_distance(corner, start, end))
+        .fold(point_box_distance(start).min(point_box_distance(end)), f32::min)
+}
+
+// Slab test, clipping the segment against each pair of box edges
+fn segment_hits_box(start: Vec2, end: Vec2, half_size: Vec2) -> bool {
+    let direction = end - start;
+    let (mut t_min, mut t_max) = (0.0f32, 1.0f32);
+
+    for axis in 0..2 {
+        if direction[axis].abs() < f32::EPSILON {
+            if start[axis].abs() > half_size[axis] {
+                return false;
+            }
+            continue;
+        }
+
+        let t1 = (-half_size[axis] - start[axis]) / direction[axis];
+        let t2 = (half_size[axis] - start[axis]) / direction[axis];
+        t_min = t_min.max(t1.min(t2));
+        t_max = t_max.min(t1.max(t2));
+
+        if t_min > t_max {
+            return false;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_4;
+
+    use super::*;
+
+    fn aabb(center: Vec2, half_size: Vec2) -> WorldShape {
+        WorldShape::Box { center, half_size, rotation: Vec2::X }
+    }
+
+    fn obb(center: Vec2, half_size: Vec2, angle: f32) -> WorldShape {
+        WorldShape::Box { center, half_size, rotation: Vec2::from_angle(angle) }
+    }
+
+    fn circle(center: Vec2, radius: f32) -> WorldShape {
+        WorldShape::Circle { center, radius }
+    }
+
+    fn capsule(start: Vec2, end: Vec2, radius: f32) -> WorldShape {
+        WorldShape::Capsule { start, end, radius }
+    }
+
+    // Quarter circle facing +x, like the melee swing
+    fn sector(center: Vec2, radius: f32) -> WorldShape {
+        WorldShape::Sector(Sector { center, radius, direction: Vec2::X, half_angle: FRAC_PI_4 })
+    }
+
+    // Checks both argument orders so every pair is exercised symmetrically
+    fn assert_hit(a: WorldShape, b: WorldShape, expected: bool) {
+        assert_eq!(a.intersects(&b), expected, "{:?} vs {:?}", a, b);
+        assert_eq!(b.intersects(&a), expected, "{:?} vs {:?}", b, a);
+    }
+
+    #[test]
+    fn aabb_vs_aabb() {
+        let a = aabb(Vec2::ZERO, Vec2::splat(10.));
+        assert_hit(a, aabb(Vec2::new(19., 0.), Vec2::splat(10.)), true);
+        assert_hit(a, aabb(Vec2::new(21., 0.), Vec2::splat(10.)), false);
+    }
+
+    #[test]
+    fn obb_vs_aabb() {
+        // A long diagonal bar misses a box sitting in the corner of the bar's axis aligned bounds
+        let bar = obb(Vec2::ZERO, Vec2::new(500., 10.), FRAC_PI_4);
+        assert_hit(bar, aabb(Vec2::new(300., -300.), Vec2::splat(20.)), false);
+        assert_hit(bar, aabb(Vec2::new(300., 300.), Vec2::splat(20.)), true);
+    }
+
+    #[test]
+    fn obb_vs_obb() {
+        let a = obb(Vec2::ZERO, Vec2::new(50., 5.), FRAC_PI_4);
+        assert_hit(a, obb(Vec2::new(0., 10.), Vec2::new(50., 5.), -FRAC_PI_4), true);
+        assert_hit(a, obb(Vec2::new(40., -40.), Vec2::new(50., 5.), FRAC_PI_4), false);
+    }
+
+    #[test]
+    fn box_vs_circle() {
+        let a = aabb(Vec2::ZERO, Vec2::splat(10.));
+        assert_hit(a, circle(Vec2::new(14., 0.), 5.), true);
+        // Just past the corner, an AABB check would report a hit here
+        assert_hit(a, circle(Vec2::new(14., 14.), 5.), false);
+
+        let bar = obb(Vec2::ZERO, Vec2::new(500., 10.), FRAC_PI_4);
+        assert_hit(bar, circle(Vec2::new(200., 200.), 5.), true);
+        assert_hit(bar, circle(Vec2::new(200., -200.), 5.), false);
+    }
+
+    #[test]
+    fn box_vs_capsule() {
+        let a = aabb(Vec2::ZERO, Vec2::splat(10.));
+        // Passes straight through without either endpoint inside
+        assert_hit(a, capsule(Vec2::new(-50., 0.), Vec2::new(50., 0.), 0.), true);
+        assert_hit(a, capsule(Vec2::new(-50., 14.), Vec2::new(50., 14.), 5.), true);
+        assert_hit(a, capsule(Vec2::new(-50., 16.), Vec2::new(50., 16.), 5.), false);
+
+        let diamond = obb(Vec2::ZERO, Vec2::splat(10.), FRAC_PI_4);
+        assert_hit(diamond, capsule(Vec2::new(13., -50.), Vec2::new(13., 50.), 0.), true);
+        assert_hit(diamond, capsule(Vec2::new(15., -50.), Vec2::new(15., 50.), 0.), false);
+    }
+
+    #[test]
+    fn circle_vs_circle() {
+        let a = circle(Vec2::ZERO, 10.);
+        assert_hit(a, circle(Vec2::new(15., 0.), 5.), true);
+        assert_hit(a, circle(Vec2::new(16., 0.), 5.), false);
+    }
+
+    #[test]
+    fn circle_vs_capsule() {
+        let a = capsule(Vec2::new(-50., 0.), Vec2::new(50., 0.), 5.);
+        assert_hit(a, circle(Vec2::new(0., 14.), 10.), true);
+        assert_hit(a, circle(Vec2::new(0., 16.), 10.), false);
+        // Rounded end caps
+        assert_hit(a, circle(Vec2::new(60., 0.), 6.), true);
+        assert_hit(a, circle(Vec2::new(60., 10.), 6.), false);
+    }
+
+    #[test]
+    fn capsule_vs_capsule() {
+        let a = capsule(Vec2::new(-50., 0.), Vec2::new(50., 0.), 2.);
+        assert_hit(a, capsule(Vec2::new(0., -50.), Vec2::new(0., 50.), 0.), true);
+        assert_hit(a, capsule(Vec2::new(-50., 5.), Vec2::new(50., 5.), 2.), false);
+        assert_hit(a, capsule(Vec2::new(53., 0.), Vec2::new(53., 50.), 2.), true);
+    }
+
+    #[test]
+    fn sector_vs_circle() {
+        let swing = sector(Vec2::ZERO, 100.);
+        assert_hit(swing, circle(Vec2::new(105., 0.), 10.), true);
+        assert_hit(swing, circle(Vec2::new(115., 0.), 10.), false);
+        // Behind the player and off to the side of the swing
+        assert_hit(swing, circle(Vec2::new(-50., 0.), 10.), false);
+        assert_hit(swing, circle(Vec2::new(20., 60.), 10.), false);
+        assert_hit(swing, circle(Vec2::new(30., 35.), 10.), true);
+    }
+
+    #[test]
+    fn sector_vs_box() {
+        let swing = sector(Vec2::ZERO, 100.);
+        assert_hit(swing, aabb(Vec2::new(50., 0.), Vec2::splat(5.)), true);
+        assert_hit(swing, aabb(Vec2::new(0., 60.), Vec2::splat(10.)), false);
+        // Box swallowing the whole swing
+        assert_hit(swing, aabb(Vec2::ZERO, Vec2::splat(500.)), true);
+        // Box edge clipping the arc only
+        assert_hit(swing, aabb(Vec2::new(105., 0.), Vec2::new(10., 40.)), true);
+    }
+
+    #[test]
+    fn sector_vs_capsule() {
+        let swing = sector(Vec2::ZERO, 100.);
+        assert_hit(swing, capsule(Vec2::new(80., -100.), Vec2::new(80., 100.), 0.), true);
+        assert_hit(swing, capsule(Vec2::new(110., -100.), Vec2::new(110., 100.), 5.), false);
+        assert_hit(swing, capsule(Vec2::new(110., -100.), Vec2::new(110., 100.), 15.), true);
+        assert_hit(swing, capsule(Vec2::new(-100., 0.), Vec2::new(-10., 0.), 5.), false);
+    }
+
+    #[test]
+    fn sector_vs_sector() {
+        let swing = sector(Vec2::ZERO, 100.);
+        assert_hit(swing, sector(Vec2::new(150., 0.), 100.), false);
+        assert_hit(swing, sector(Vec2::new(50., 0.), 100.), true);
+        let facing = WorldShape::Sector(Sector { center: Vec2::new(150., 0.), radius: 100., direction: -Vec2::X, half_angle: FRAC_PI_4 });
+        assert_hit(swing, facing, true);
+    }
+
+    #[test]
+    fn rotated_colliders_follow_the_transform() {
+        let transform = Transform::from_xyz(100., 0., 0.).with_rotation(Quat::from_rotation_z(FRAC_PI_4));
+
+        let bar = WorldShape::from_collider(ColliderShape::Obb { half_size: Vec2::new(50., 5.) }, &transform);
+        let line = WorldShape::from_collider(ColliderShape::Capsule { half_length: 50., radius: 5. }, &transform);
+        let aabb = WorldShape::from_collider(ColliderShape::Aabb { half_size: Vec2::new(50., 5.) }, &transform);
+
+        let on_diagonal = circle(Vec2::new(130., 30.), 1.);
+        assert_hit(bar, on_diagonal, true);
+        assert_hit(line, on_diagonal, true);
+        assert_hit(aabb, on_diagonal, false);
+
+        assert!(bar.bounds().contains(Vec2::new(135., 35.)));
+
+        let swing = WorldShape::from_collider(ColliderShape::Sector { radius: 50., half_angle: 0.1 }, &transform);
+        assert_hit(swing, on_diagonal, true);
+        assert_hit(swing, circle(Vec2::new(140., 0.), 1.), false);
+    }
+}
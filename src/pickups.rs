@@ -0,0 +1,287 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    clean_dead,
+    collision::{build_broadphase, detect_collisions},
+    components::{
+        Attracted, Collider, ColliderShape, CollisionLayer, CollisionLayers, Cooldowns, EnemyKind, GameState, Health,
+        Pickup, Player, Resettable,
+    },
+    enemy::archetype,
+    progression::Experience,
+    rng::GameRng,
+    CollisionEvent, EnemyKilled, PLAYER_MAX_HEALTH,
+};
+
+// Pickups inside this radius start flying at the player
+const ATTRACT_RADIUS: f32 = 200.;
+const ATTRACT_SPEED: f32 = 600.;
+const PICKUP_SIZE: f32 = 18.;
+// Dropped pickups are scattered this far around the body so they don't stack
+const DROP_SCATTER: f32 = 30.;
+const HEALTH_ORB_HEAL: i32 = 50;
+
+// Independent chance for each pickup to drop from an enemy
+pub struct Drop {
+    pub pickup: Pickup,
+    pub chance: f64,
+}
+
+// What an enemy can drop on top of its XP gem, which always drops
+pub fn drop_table(kind: EnemyKind) -> &'static [Drop] {
+    match kind {
+        EnemyKind::Oni | EnemyKind::Splitling => &[
+            Drop { pickup: Pickup::Health(HEALTH_ORB_HEAL), chance: 0.03 },
+            Drop { pickup: Pickup::CooldownScroll, chance: 0.005 },
+            Drop { pickup: Pickup::Magnet, chance: 0.003 },
+        ],
+        EnemyKind::Runner => &[
+            Drop { pickup: Pickup::Health(HEALTH_ORB_HEAL), chance: 0.05 },
+            Drop { pickup: Pickup::Magnet, chance: 0.01 },
+        ],
+        EnemyKind::Spitter | EnemyKind::Splitter => &[
+            Drop { pickup: Pickup::Health(HEALTH_ORB_HEAL), chance: 0.1 },
+            Drop { pickup: Pickup::CooldownScroll, chance: 0.03 },
+            Drop { pickup: Pickup::Magnet, chance: 0.02 },
+        ],
+        EnemyKind::Brute => &[
+            Drop { pickup: Pickup::Health(HEALTH_ORB_HEAL), chance: 0.3 },
+            Drop { pickup: Pickup::CooldownScroll, chance: 0.1 },
+            Drop { pickup: Pickup::Magnet, chance: 0.05 },
+        ],
+    }
+}
+
+pub struct PickupPlugin;
+
+impl Plugin for PickupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                drop_pickups.after(clean_dead),
+                attract_pickups.before(build_broadphase),
+                collect_pickups.after(detect_collisions),
+            )
+                .run_if(in_state(GameState::Running)),
+        );
+    }
+}
+
+pub fn spawn_pickup(commands: &mut Commands, pickup: Pickup, position: Vec2) {
+    let color = match pickup {
+        Pickup::Health(_) => Color::srgb(0.9, 0.2, 0.3),
+        Pickup::Xp(_) => Color::srgb(0.3, 0.8, 1.0),
+        Pickup::CooldownScroll => Color::srgb(0.95, 0.85, 0.4),
+        Pickup::Magnet => Color::srgb(0.7, 0.3, 0.9),
+    };
+
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(Vec2::splat(PICKUP_SIZE)),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(position.extend(5.)),
+            ..Default::default()
+        },
+        pickup,
+        Collider::from_shape(ColliderShape::Circle {
+            radius: PICKUP_SIZE / 2.,
+        }),
+        CollisionLayers::new(CollisionLayer::Pickup),
+        Resettable,
+    ));
+}
+
+fn drop_pickups(mut commands: Commands, mut kill_reader: EventReader<EnemyKilled>, mut rng: ResMut<GameRng>) {
+    for kill in kill_reader.read() {
+        spawn_pickup(&mut commands, Pickup::Xp(archetype(kill.kind).score), kill.position);
+
+        for drop in drop_table(kill.kind) {
+            if rng.gen_bool(drop.chance) {
+                let scatter = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU)) * DROP_SCATTER;
+                spawn_pickup(&mut commands, drop.pickup, kill.position + scatter);
+            }
+        }
+    }
+}
+
+fn attract_pickups(
+    mut commands: Commands,
+    time: Res<Time>,
+    player_query: Query<&Transform, With<Player>>,
+    mut pickup_query: Query<(Entity, &mut Transform, Has<Attracted>), (With<Pickup>, Without<Player>)>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let player_position = player_transform.translation.truncate();
+
+    for (entity, mut transform, attracted) in pickup_query.iter_mut() {
+        let to_player = player_position - transform.translation.truncate();
+
+        // Once a pickup starts coming it keeps coming, even if the player runs
+        if !attracted {
+            if to_player.length() > ATTRACT_RADIUS {
+                continue;
+            }
+            commands.entity(entity).insert(Attracted);
+        }
+
+        let step = to_player.clamp_length_max(ATTRACT_SPEED * time.delta_seconds());
+        transform.translation += step.extend(0.);
+    }
+}
+
+fn collect_pickups(
+    mut commands: Commands,
+    mut collision_reader: EventReader<CollisionEvent>,
+    pickup_query: Query<&Pickup>,
+    mut player_query: Query<(&mut Health, &mut Cooldowns), With<Player>>,
+    mut experience: ResMut<Experience>,
+    all_pickups: Query<Entity, With<Pickup>>,
+) {
+    for event in collision_reader.read() {
+        if event.other_layer != CollisionLayer::Player {
+            continue;
+        }
+        let Ok(pickup) = pickup_query.get(event.entity) else {
+            continue;
+        };
+        let Ok((mut health, mut cooldowns)) = player_query.get_mut(event.other) else {
+            continue;
+        };
+
+        match *pickup {
+            Pickup::Health(amount) => health.heal(amount, PLAYER_MAX_HEALTH),
+            Pickup::Xp(xp) => experience.add(xp),
+            Pickup::CooldownScroll => cooldowns.reduce_all(f32::MAX),
+            Pickup::Magnet => {
+                for entity in all_pickups.iter() {
+                    commands.entity(entity).try_insert(Attracted);
+                }
+            }
+        }
+        commands.entity(event.entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::state::app::StatesPlugin;
+
+    use super::*;
+    use crate::{abilities::AbilityDef, collision::CollisionPlugin, components::Invulnerability};
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin, CollisionPlugin, PickupPlugin))
+            .insert_state(GameState::Running)
+            .init_resource::<Experience>()
+            .init_resource::<GameRng>()
+            .add_event::<CollisionEvent>()
+            .add_event::<EnemyKilled>()
+            .add_systems(FixedUpdate, clean_dead);
+        app
+    }
+
+    fn spawn_player(app: &mut App) -> Entity {
+        let mut cooldowns = Cooldowns::new();
+        cooldowns.configure(Handle::<AbilityDef>::weak_from_u128(1).id(), 5.0, 1);
+
+        app.world_mut()
+            .spawn((
+                Player,
+                Health { hp: 100 },
+                cooldowns,
+                Transform::default(),
+                Collider::from_shape(ColliderShape::Circle { radius: 50. }),
+                CollisionLayers::new(CollisionLayer::Player),
+            ))
+            .id()
+    }
+
+    fn tick(app: &mut App, seconds: f32) {
+        app.world_mut().resource_mut::<Time>().advance_by(Duration::from_secs_f32(seconds));
+        app.world_mut().run_schedule(FixedUpdate);
+    }
+
+    #[test]
+    fn enemies_always_drop_their_xp() {
+        let mut app = test_app();
+        app.world_mut().send_event(EnemyKilled {
+            kind: EnemyKind::Brute,
+            position: Vec2::new(1000., 0.),
+            ability: None,
+        });
+
+        tick(&mut app, 0.);
+
+        let mut pickups = app.world_mut().query::<&Pickup>();
+        let pickups: Vec<Pickup> = pickups.iter(app.world()).copied().collect();
+        assert!(pickups.contains(&Pickup::Xp(archetype(EnemyKind::Brute).score)));
+    }
+
+    #[test]
+    fn nearby_pickups_fly_in_and_get_collected() {
+        let mut app = test_app();
+        let player = spawn_player(&mut app);
+        app.world_mut().resource_mut::<Experience>().xp = 0;
+        let commands = &mut app.world_mut().commands();
+        spawn_pickup(commands, Pickup::Xp(3), Vec2::new(150., 0.));
+        spawn_pickup(commands, Pickup::Health(50), Vec2::new(180., 0.));
+        spawn_pickup(commands, Pickup::CooldownScroll, Vec2::new(1000., 0.));
+        app.world_mut().flush();
+        app.world_mut().get_mut::<Health>(player).unwrap().hp = 10;
+
+        for _ in 0..10 {
+            tick(&mut app, 0.05);
+        }
+
+        assert_eq!(app.world().resource::<Experience>().xp, 3);
+        assert_eq!(app.world().get::<Health>(player).unwrap().hp, 60);
+        // Too far away to be pulled in
+        let mut pickups = app.world_mut().query::<&Pickup>();
+        assert_eq!(pickups.iter(app.world()).collect::<Vec<_>>(), vec![&Pickup::CooldownScroll]);
+    }
+
+    #[test]
+    fn magnets_pull_in_everything() {
+        let mut app = test_app();
+        let player = spawn_player(&mut app);
+        let commands = &mut app.world_mut().commands();
+        spawn_pickup(commands, Pickup::Magnet, Vec2::new(20., 0.));
+        spawn_pickup(commands, Pickup::CooldownScroll, Vec2::new(1000., 0.));
+        app.world_mut().flush();
+
+        for _ in 0..50 {
+            tick(&mut app, 0.05);
+        }
+
+        let mut pickups = app.world_mut().query::<&Pickup>();
+        assert_eq!(pickups.iter(app.world()).count(), 0);
+        let ability = Handle::<AbilityDef>::weak_from_u128(1).id();
+        assert!(app.world().get::<Cooldowns>(player).unwrap().get_cooldown(ability).unwrap() == 0.0);
+    }
+
+    #[test]
+    fn i_frames_dont_stop_collection() {
+        let mut app = test_app();
+        let player = spawn_player(&mut app);
+        app.world_mut().entity_mut(player).insert(Invulnerability {
+            timer: Timer::from_seconds(10., TimerMode::Once),
+        });
+        app.world_mut().resource_mut::<Experience>().xp = 0;
+        spawn_pickup(&mut app.world_mut().commands(), Pickup::Xp(2), Vec2::new(10., 0.));
+        app.world_mut().flush();
+
+        tick(&mut app, 0.05);
+
+        assert_eq!(app.world().resource::<Experience>().xp, 2);
+    }
+}
@@ -1,31 +1,57 @@
-use std::f32::consts::PI;
+use std::f32::consts::FRAC_PI_2;
 
 use crate::{
-    aoe_sound, dash_sound, play_empty_swing, ranged_sound, spawn_bigfoot, GameTextures, MouseCoords,
-    BASE_SPEED, SPRITE_SCALE, SPRITE_SIZE,
+    abilities::{AbilityBook, AbilityDef, AbilityModifiers, AbilityShape},
+    beat::{BeatClock, BEAT_BONUS_DAMAGE, BEAT_COOLDOWN_REFUND, BEAT_WINDOW},
+    controls::{Action, ActionState},
+    collision::build_broadphase,
+    rng::CosmeticRng,
+    play_random_sfx, spawn_bigfoot, GameTextures, MouseCoords,
+    BASE_SPEED, PLAYER_MAX_HEALTH, SPRITE_SCALE, SPRITE_SIZE,
 };
 use crate::components::{
-    Ability, Collider, Cooldowns, GameState, Health, Invulnerability, Lifetime, Line, Player,
-    PointMarker, Points, Resettable, Velocity,
+    Boomerang, Collider, ColliderShape, CollisionLayer, CollisionLayers, Cooldowns, DamageOnHit, Dash, GameState, Health, HitRegistry, Invulnerability, Lifetime, Line, Player, Projectile,
+    Resettable, Velocity,
 };
-use bevy::prelude::*;
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
+        app.add_systems(Startup, setup_hitbox_visuals)
+        .add_systems(
             OnEnter(GameState::Running),
             (player_spawn_system, spawn_bigfoot.after(player_spawn_system)),
         )
         .add_systems(
             FixedUpdate,
-            (player_movement_system, player_keyboard_event_system, ability_system)
+            (
+                player_movement_system,
+                player_keyboard_event_system,
+                ability_system,
+                dash_motion.after(ability_system).before(build_broadphase),
+            )
                 .run_if(in_state(GameState::Running)),
         );
     }
 }
 
+// Material shared by every melee and AoE hitbox, meshes follow the ability's range
+#[derive(Resource)]
+struct HitboxVisuals {
+    material: Handle<ColorMaterial>,
+}
+
+fn setup_hitbox_visuals(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(HitboxVisuals {
+        material: materials.add(Color::srgba(1.0, 0.1, 0.1, 0.5)),
+    });
+}
+
 pub fn player_spawn_system(
     mut commands: Commands,
     game_textures: Res<GameTextures>,
@@ -46,9 +72,12 @@ pub fn player_spawn_system(
                 ..Default::default()
             },
             Health {
-                hp: 500
+                hp: PLAYER_MAX_HEALTH
             },
-            Collider::new(Vec2::splat(SPRITE_SIZE.0 * SPRITE_SCALE)),
+            Collider::from_shape(ColliderShape::Circle {
+                radius: SPRITE_SIZE.0 * SPRITE_SCALE / 2.,
+            }),
+            CollisionLayers::new(CollisionLayer::Player),
             Cooldowns::new(),
             Player,
             Velocity {
@@ -60,18 +89,19 @@ pub fn player_spawn_system(
 }
 
 fn player_keyboard_event_system(
-    kb: Res<ButtonInput<KeyCode>>,
+    actions: Res<ActionState>,
     mut query: Query<&mut Velocity, With<Player>>
 ) {
     if let Ok(mut velocity) = query.get_single_mut() {
-        velocity.x = if kb.pressed(KeyCode::KeyA) {
-            if kb.pressed(KeyCode::KeyW) || kb.pressed(KeyCode::KeyS) {
+        let vertical = actions.pressed(Action::MoveUp) || actions.pressed(Action::MoveDown);
+        velocity.x = if actions.pressed(Action::MoveLeft) {
+            if vertical {
                 -1. / 2.
             } else {
                 -1.
             }
-        } else if kb.pressed(KeyCode::KeyD) {
-            if kb.pressed(KeyCode::KeyW) || kb.pressed(KeyCode::KeyS){
+        } else if actions.pressed(Action::MoveRight) {
+            if vertical {
                 1. / 2.
             } else {
                 1.
@@ -80,9 +110,9 @@ fn player_keyboard_event_system(
             0.
         };
 
-        velocity.y = if kb.pressed(KeyCode::KeyS) {
+        velocity.y = if actions.pressed(Action::MoveDown) {
             -1.
-        } else if kb.pressed(KeyCode::KeyW) {
+        } else if actions.pressed(Action::MoveUp) {
             1.
         } else {
             0.
@@ -91,7 +121,7 @@ fn player_keyboard_event_system(
 }
 
 fn player_movement_system(
-    mut query: Query<(&Velocity, &mut Transform), With<Player>>,
+    mut query: Query<(&Velocity, &mut Transform), (With<Player>, Without<Dash>)>,
     time: Res<Time>
 ) {
     for (velocity, mut transform) in query.iter_mut() {
@@ -104,262 +134,463 @@ fn player_movement_system(
 
 fn ability_system(
     mut commands: Commands,
-    kb: Res<ButtonInput<KeyCode>>,
-    mut cooldown_query: Query<&mut Cooldowns>,
+    actions: Res<ActionState>,
+    mut player_query: Query<(Entity, &Transform, &mut Cooldowns, Has<Dash>), With<Player>>,
     mouse_coords: Res<MouseCoords>,
-    player_query: Query<(Entity, &mut Transform), With<Player>>,
+    book: Res<AbilityBook>,
+    defs: Res<Assets<AbilityDef>>,
+    modifiers: Res<AbilityModifiers>,
+    beat_clock: Res<BeatClock>,
+    mut sfx_rng: ResMut<CosmeticRng>,
     game_textures: Res<GameTextures>,
-    points: ResMut<Points>,
-    mut asset_server: Res<AssetServer>,
+    hitbox_visuals: Res<HitboxVisuals>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
 ) {
-    if let Ok(mut cooldowns) = cooldown_query.get_single_mut() {
-        if kb.just_pressed(KeyCode::KeyE) {
-            if cooldowns.is_ready(Ability::Ranged) {
-                ranged_attack(
-                    &mut commands,
-                    player_query,
-                    mouse_coords,
-                    game_textures);
-                cooldowns.reset(Ability::Ranged);
-                ranged_sound(&mut asset_server, &mut commands);
-            } else {
-                println!("Ranged ability on cooldown!");
-            }
-        } else if kb.just_pressed(KeyCode::KeyF) {
-            if cooldowns.is_ready(Ability::Dash) {
-                dash_attack(
-                    &mut commands,
-                    player_query,
-                    mouse_coords,
-                    game_textures);
-                cooldowns.reset(Ability::Dash);
-                dash_sound(&mut asset_server, &mut commands);
-            } else {
-                println!("Dash is on cooldown!");
+    let Ok((player_entity, transform, mut cooldowns, dashing)) = player_query.get_single_mut() else {
+        return;
+    };
 
-            }
-        } else if kb.just_pressed(KeyCode::KeyQ) {
-            if cooldowns.is_ready(Ability::Attack) {
-                melee_attack(
-                    &mut commands,
-                    player_query,
-                    mouse_coords,
-                    game_textures,
-                    points);
-                cooldowns.reset(Ability::Attack);
-                play_empty_swing(asset_server, &mut commands);
-            } else {
-                println!("Arc ability is on cooldown!");
-            }
-        } else if kb.just_pressed(KeyCode::KeyT) {
-            if cooldowns.is_ready(Ability::Aoe) {
-                aoe_attack(
-                    &mut commands, 
-                    player_query, 
-                    game_textures, 
-                    points);
-                cooldowns.reset(Ability::Aoe);
-                aoe_sound(&asset_server, &mut commands)
-            } else {
-                println!("AOE is on cooldown!");
-            }
+    // One ability per tick, the first one in the book whose action went down wins
+    let pressed = book
+        .abilities
+        .iter()
+        .filter_map(|handle| Some((handle.id(), defs.get(handle)?)))
+        .find(|(_, def)| actions.just_pressed(def.action));
+
+    let Some((ability, def)) = pressed else {
+        return;
+    };
+    let def = &modifiers.effective(ability, def);
+
+    if !cooldowns.is_ready(ability) {
+        debug!("{} is on cooldown", def.name);
+        return;
+    }
+    // A second dash would take over the first one's motion and leave its hitbox behind
+    if dashing && matches!(def.shape, AbilityShape::Dash { .. }) {
+        return;
+    }
+
+    let player_position = Vec2::new(transform.translation.x, transform.translation.y);
+    let aim = Vec2::new(mouse_coords.x, mouse_coords.y) - player_position;
+    // Casting in time with the music hits harder and comes back sooner
+    let on_beat = beat_clock.on_beat(BEAT_WINDOW);
+    let hit = DamageOnHit {
+        amount: if on_beat { def.damage + BEAT_BONUS_DAMAGE } else { def.damage },
+        kind: ability,
+        knockback: def.knockback,
+        stun: def.stun,
+    };
+
+    match def.shape {
+        AbilityShape::Sector { half_angle } => {
+            // Bevy builds sectors around +y while colliders face +x
+            let mesh = Mesh::from(CircularSector::new(def.range, half_angle))
+                .rotated_by(Quat::from_rotation_z(-FRAC_PI_2));
+            melee_attack(
+                &mut commands,
+                player_position,
+                aim,
+                def,
+                half_angle,
+                hit,
+                meshes.add(mesh),
+                &hitbox_visuals);
+        }
+        AbilityShape::Circle => {
+            aoe_attack(
+                &mut commands,
+                player_position,
+                def,
+                hit,
+                meshes.add(Circle::new(def.range)),
+                &hitbox_visuals);
+        }
+        AbilityShape::Projectile { .. } => {
+            ranged_attack(
+                &mut commands,
+                player_entity,
+                player_position,
+                aim,
+                def,
+                hit,
+                &game_textures);
+        }
+        AbilityShape::Dash { radius } => {
+            dash_attack(
+                &mut commands,
+                player_entity,
+                player_position,
+                aim,
+                def,
+                radius,
+                hit,
+                &game_textures,
+                time.delta_seconds());
         }
     }
+
+    cooldowns.reset(ability);
+    if on_beat {
+        cooldowns.refund(ability, def.cooldown * BEAT_COOLDOWN_REFUND);
+    }
+    play_random_sfx(&asset_server, &mut commands, &def.sfx, def.volume, &mut sfx_rng.0);
 }
 
 fn ranged_attack(
     commands: &mut Commands,
-    player_query: Query<(Entity, &mut Transform), With<Player>>,
-    mouse_coords: Res<MouseCoords>,
-    game_textures: Res<GameTextures>,
-
+    player_entity: Entity,
+    player_position: Vec2,
+    aim: Vec2,
+    def: &AbilityDef,
+    hit: DamageOnHit,
+    game_textures: &GameTextures,
 ) {
-    if let Ok((_, transform)) = player_query.get_single() {
-        let player_position = Vec2::new(transform.translation.x, transform.translation.y);
-        let mouse_position = Vec2::new(mouse_coords.x, mouse_coords.y);
-
-
-        // Calculate the direction from the player to the mouse
-        let direction = (mouse_position - player_position).normalize();
-
-        // Set the desired line length
-        let line_length = 1100.0;
-
-        // Calculate the endpoint of the line
-        let end_point = player_position + direction * line_length;
-
-        // Calculate the midpoint of the line for positioning the sprite
-        let midpoint = (player_position + end_point) / 2.0;
+    let AbilityShape::Projectile { length, width, speed, boomerang, pierce } = def.shape else {
+        return;
+    };
 
-        // Calculate the angle for proper rotation
-        let angle = direction.y.atan2(direction.x);
+    let size = Vec2::new(length, width);
+    let direction = aim.normalize_or(Vec2::X);
+    // Time to cover the range, after which it turns back or breaks
+    let flight = def.range / speed;
 
-        commands.spawn((
-                SpriteBundle {
-                    texture: game_textures.line.clone(),
-                    transform: Transform {
-                        translation: Vec3::new(midpoint.x, midpoint.y, 1.),
-                        rotation: Quat::from_rotation_z(angle),
-                        scale: Vec3::new(1100.0, SPRITE_SCALE, 0.),
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                },
-                Collider::new(Vec2::new(line_length, SPRITE_SIZE.0)),
-                Line,
-                Lifetime {
-                    timer: Timer::from_seconds(0.1, TimerMode::Once),
+    let mut projectile = commands.spawn((
+            SpriteBundle {
+                texture: game_textures.line.clone(),
+                transform: Transform {
+                    translation: player_position.extend(1.),
+                    rotation: Quat::from_rotation_z(direction.to_angle()),
+                    scale: size.extend(0.),
                 },
-                Resettable,
+                ..Default::default()
+            },
+            Collider::from_shape(ColliderShape::Obb {
+                half_size: size / 2.,
+            }),
+            CollisionLayers::new(CollisionLayer::PlayerHitbox),
+            Projectile { direction, speed },
+            hit,
+            pierce.map_or_else(HitRegistry::default, HitRegistry::with_limit),
+            Resettable,
+    ));
+
+    if boomerang {
+        projectile.insert((
+            Boomerang {
+                owner: player_entity,
+                timer: Timer::from_seconds(flight, TimerMode::Once),
+                returning: false,
+            },
+            // In case it never makes it back
+            Lifetime {
+                timer: Timer::from_seconds(def.lifetime, TimerMode::Once),
+            },
         ));
+    } else {
+        projectile.insert(Lifetime {
+            timer: Timer::from_seconds(flight.min(def.lifetime), TimerMode::Once),
+        });
     }
 }
 
 fn dash_attack(
     commands: &mut Commands,
-    mut query: Query<(Entity, &mut Transform), With<Player>>,
-    mouse_coords: Res<MouseCoords>,
-    game_textures: Res<GameTextures>,
+    player_entity: Entity,
+    player_position: Vec2,
+    aim: Vec2,
+    def: &AbilityDef,
+    radius: f32,
+    hit: DamageOnHit,
+    game_textures: &GameTextures,
+    tick: f32,
 ) {
-    if let Ok((player_entity, mut transform)) = query.get_single_mut() {
-        let player_position = Vec2::new(transform.translation.x, transform.translation.y);
-        let mouse_position = Vec2::new(mouse_coords.x, mouse_coords.y);
-        let direction = mouse_position - player_position;
-        let length = direction.length();
-
-        let midpoint = player_position + direction / 2.;
-
-        let angle = direction.y.atan2(direction.x);
-
-        commands.spawn((
-                SpriteBundle {
-                    texture: game_textures.line.clone(),
-                    transform: Transform {
-                        translation: Vec3::new(midpoint.x, midpoint.y, 0.),
-                        rotation: Quat::from_rotation_z(angle),
-                        scale: Vec3::new(length, SPRITE_SCALE, 0.),
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                },
-                Collider::new(Vec2::new(length, SPRITE_SIZE.0)),
-                Line,
-                Lifetime {
-                    timer: Timer::from_seconds(0.1, TimerMode::Once),
+    // Head for the cursor, but never further than the range
+    let offset = aim.clamp_length_max(def.range);
+    let angle = offset.normalize_or(Vec2::X).to_angle();
+    let ticks = (def.lifetime / tick).round().max(1.) as u32;
+
+    let hitbox = commands.spawn((
+            SpriteBundle {
+                texture: game_textures.line.clone(),
+                transform: Transform {
+                    translation: player_position.extend(0.),
+                    rotation: Quat::from_rotation_z(angle),
+                    scale: Vec3::new(0., SPRITE_SCALE, 0.),
                 },
-                Resettable,
-        ));
-        commands.entity(player_entity).insert(Invulnerability {
-            timer: Timer::from_seconds(1.0, TimerMode::Once)
-        });
+                ..Default::default()
+            },
+            // Grows with the dash so it sweeps the player's body along the path
+            Collider::from_shape(ColliderShape::Capsule {
+                half_length: 0.,
+                radius,
+            }),
+            CollisionLayers::new(CollisionLayer::PlayerHitbox),
+            Line,
+            hit,
+            HitRegistry::default(),
+            // dash_motion removes it a tick after the last step, this only catches a player who
+            // died mid-dash
+            Lifetime {
+                timer: Timer::from_seconds((ticks + 2) as f32 * tick, TimerMode::Once),
+            },
+            Resettable,
+    )).id();
+
+    commands.entity(player_entity).insert((
+        Dash {
+            origin: player_position,
+            step: offset / ticks as f32,
+            ticks_left: ticks,
+            radius,
+            hitbox,
+        },
+        Invulnerability {
+            timer: Timer::from_seconds((ticks + 1) as f32 * tick, TimerMode::Once),
+        },
+    ));
+}
+
+fn dash_motion(
+    mut commands: Commands,
+    mut player_query: Query<(Entity, &mut Transform, &mut Dash), With<Player>>,
+    mut hitbox_query: Query<(&mut Transform, &mut Collider), Without<Player>>,
+) {
+    for (entity, mut transform, mut dash) in player_query.iter_mut() {
+        // The last step was swept on the previous tick, so the hitbox got its collision pass
+        if dash.ticks_left == 0 {
+            commands.entity(entity).remove::<Dash>();
+            commands.entity(dash.hitbox).despawn();
+            continue;
+        }
+
+        transform.translation += dash.step.extend(0.);
+        dash.ticks_left -= 1;
+
+        let position = transform.translation.truncate();
+        let swept = (position - dash.origin).length();
 
-        transform.translation.x = mouse_position.x;
-        transform.translation.y = mouse_position.y;
+        if let Ok((mut hitbox_transform, mut collider)) = hitbox_query.get_mut(dash.hitbox) {
+            hitbox_transform.translation = ((dash.origin + position) / 2.).extend(0.);
+            hitbox_transform.scale.x = swept;
+            collider.shape = ColliderShape::Capsule {
+                half_length: swept / 2.,
+                radius: dash.radius,
+            };
+        }
     }
 }
 
 fn melee_attack(
     commands: &mut Commands,
-    player_query: Query<(Entity, &mut Transform), With<Player>>,
-    mouse_coords: Res<MouseCoords>,
-    game_textures: Res<GameTextures>,
-    mut points: ResMut<Points>,
+    player_position: Vec2,
+    aim: Vec2,
+    def: &AbilityDef,
+    half_angle: f32,
+    hit: DamageOnHit,
+    mesh: Handle<Mesh>,
+    hitbox_visuals: &HitboxVisuals,
 ) {
-    if let Ok((_, transform)) = player_query.get_single() {
-        let player_position = Vec2::new(transform.translation.x, transform.translation.y);
-        let mouse_position = Vec2::new(mouse_coords.x, mouse_coords.y);
-
-        let direction = (mouse_position - player_position).normalize();
-        let start_angle = direction.y.atan2(direction.x);
-
-        let max_radius = 250.0; // Max radius for the arc
-        let theta = 0.0725; // Smaller theta for finer increments
-        let arc_span = PI / 2.0; // 90 degrees in radians
-        let radius_step = 10.0; // Distance between each concentric arc
-
-
-        let arc_segments = (arc_span / theta) as i32;
-
-        points.0.clear();
-
-        for radius in (radius_step as i32..=max_radius as i32).step_by(radius_step as usize) {
-            for i in 0..=arc_segments {
-                let angle = start_angle - (arc_span / 2.) + i as f32 * theta;
-                let arc_point = Vec2::new(
-                    player_position.x + radius as f32 * angle.cos(),
-                    player_position.y + radius as f32 * angle.sin(),
-                );
-
-                points.0.push(arc_point);
-
-                commands.spawn((
-                        SpriteBundle {
-                            texture: game_textures.line.clone(),
-                            transform: Transform {
-                                translation: Vec3::new(arc_point.x, arc_point.y, 0.),
-                                scale: Vec3::new(5., 5., 0.),
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        },
-                        Collider::new(Vec2::new(5., 5.)),
-                        PointMarker,
-                        Lifetime {
-                            timer: Timer::from_seconds(0.1, TimerMode::Once),
-                        },
-                        Resettable,
-                ));
-            }
-        }
-    }
-}
+    let angle = aim.normalize_or(Vec2::X).to_angle();
 
+    commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: mesh.into(),
+                material: hitbox_visuals.material.clone(),
+                transform: Transform {
+                    translation: player_position.extend(0.),
+                    rotation: Quat::from_rotation_z(angle),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Collider::from_shape(ColliderShape::Sector {
+                radius: def.range,
+                half_angle,
+            }),
+            CollisionLayers::new(CollisionLayer::PlayerHitbox),
+            hit,
+            HitRegistry::default(),
+            Lifetime {
+                timer: Timer::from_seconds(def.lifetime, TimerMode::Once),
+            },
+            Resettable,
+    ));
+}
 
 fn aoe_attack(
     commands: &mut Commands,
-    player_query: Query<(Entity, &mut Transform), With<Player>>,
-    game_textures: Res<GameTextures>,
-    mut points: ResMut<Points>
+    player_position: Vec2,
+    def: &AbilityDef,
+    hit: DamageOnHit,
+    mesh: Handle<Mesh>,
+    hitbox_visuals: &HitboxVisuals,
 ) {
-    if let Ok((_, transform)) = player_query.get_single() {
-        let player_position = Vec2::new(transform.translation.x, transform.translation.y);
-
-        let max_radius = 300.;
-        let theta = 0.0725;
-        let total_angle = 2.0 * PI;
-        let radius_step = 10.;
-        let arc_segments = (total_angle / theta) as i32;
-
-        points.0.clear();
-
-        for radius in(0..=max_radius as i32).step_by(radius_step as usize) {
-            for i in 0..=arc_segments {
-                let angle = i as f32 * theta;
-                let circle_point = Vec2::new(
-                    player_position.x + radius as f32 * angle.cos(), 
-                    player_position.y + radius as f32 * angle.sin(),
-                );
-
-                points.0.push(circle_point);
-
-                commands.spawn((
-                        SpriteBundle {
-                            texture: game_textures.line.clone(),
-                            transform: Transform {
-                                translation: Vec3::new(circle_point.x, circle_point.y, 0.),
-                                scale: Vec3::new(5., 5., 0.),
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        },
-                        Collider::new(Vec2::new(5., 5.)),
-                        PointMarker,
-                        Lifetime {
-                            timer: Timer::from_seconds(0.1, TimerMode::Once),
-                        },
-                        Resettable,
-                ));
-            }
+    commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: mesh.into(),
+                material: hitbox_visuals.material.clone(),
+                transform: Transform::from_translation(player_position.extend(0.)),
+                ..Default::default()
+            },
+            Collider::from_shape(ColliderShape::Circle {
+                radius: def.range,
+            }),
+            CollisionLayers::new(CollisionLayer::PlayerHitbox),
+            hit,
+            HitRegistry::default(),
+            Lifetime {
+                timer: Timer::from_seconds(def.lifetime, TimerMode::Once),
+            },
+            Resettable,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{ecs::system::RunSystemOnce, state::app::StatesPlugin};
+
+    use super::*;
+    use crate::{clean_dead, collision::CollisionPlugin, damage::DamagePlugin, CollisionEvent, EnemyKilled, PlayerHit};
+    use crate::components::{Enemy, Score};
+
+    #[test]
+    fn dash_sweeps_a_capped_distance_and_hits_along_the_way() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin, CollisionPlugin, DamagePlugin))
+            .init_asset::<AudioSource>()
+            .init_resource::<CosmeticRng>()
+            .insert_state(GameState::Running)
+            .insert_resource(Score::new())
+            .insert_resource(MouseCoords { x: 1000., y: 0. })
+            .insert_resource(GameTextures {
+                player: Handle::default(),
+                enemy: Handle::default(),
+                line: Handle::default(),
+                map: Handle::default(),
+            })
+            .add_event::<CollisionEvent>()
+            .add_event::<PlayerHit>()
+            .add_event::<EnemyKilled>()
+            .add_systems(FixedUpdate, (dash_motion.before(build_broadphase), clean_dead));
+
+        let player = app.world_mut().spawn((Player, Transform::default())).id();
+        let enemy = app
+            .world_mut()
+            .spawn((
+                Enemy,
+                Health { hp: 10 },
+                Transform::from_xyz(300., 0., 0.),
+                Collider::from_shape(ColliderShape::Circle { radius: 20. }),
+                CollisionLayers::new(CollisionLayer::Enemy),
+            ))
+            .id();
+
+        let def = AbilityDef::parse_ron(&std::fs::read("assets/abilities/dash.ability.ron").unwrap()).unwrap();
+        let AbilityShape::Dash { radius } = def.shape else {
+            panic!("dash.ability.ron is not a dash");
+        };
+        let range = def.range;
+
+        app.world_mut().run_system_once(
+            move |mut commands: Commands, mouse_coords: Res<MouseCoords>, game_textures: Res<GameTextures>| {
+                let aim = Vec2::new(mouse_coords.x, mouse_coords.y);
+                let hit = DamageOnHit {
+                    amount: 1,
+                    kind: Handle::<AbilityDef>::weak_from_u128(1).id(),
+                    knockback: 0.,
+                    stun: 0.,
+                };
+                dash_attack(&mut commands, player, Vec2::ZERO, aim, &def, radius, hit, &game_textures, 1. / 64.)
+            },
+        );
+        assert!(app.world().get::<Invulnerability>(player).is_some());
+
+        let ticks = app.world().get::<Dash>(player).unwrap().ticks_left;
+        for _ in 0..=ticks {
+            app.world_mut().run_schedule(FixedUpdate);
         }
+
+        let position = app.world().get::<Transform>(player).unwrap().translation;
+        assert!((position.x - range).abs() < 1e-3);
+        assert!(app.world().get::<Dash>(player).is_none());
+        assert_eq!(app.world().get::<Health>(enemy).unwrap().hp, 9);
+
+        let mut hitboxes = app.world_mut().query::<&DamageOnHit>();
+        assert_eq!(hitboxes.iter(app.world()).count(), 0);
+    }
+
+    #[test]
+    fn dash_charges_refill_one_at_a_time() {
+        let dash = Handle::<AbilityDef>::weak_from_u128(1).id();
+        let mut cooldowns = Cooldowns::new();
+        cooldowns.configure(dash, 5.0, 2);
+        assert!(!cooldowns.is_ready(dash));
+
+        cooldowns.tick(Duration::from_secs(5));
+        cooldowns.tick(Duration::from_secs(5));
+        assert_eq!(cooldowns.get_charges(dash).unwrap().stored, 2);
+
+        cooldowns.reset(dash);
+        cooldowns.reset(dash);
+        assert!(!cooldowns.is_ready(dash));
+
+        cooldowns.tick(Duration::from_secs(5));
+        assert!(cooldowns.is_ready(dash));
+        assert_eq!(cooldowns.get_charges(dash).unwrap().stored, 1);
+    }
+
+    #[test]
+    fn dashing_again_mid_dash_is_refused() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<AbilityDef>()
+            .init_asset::<Mesh>()
+            .init_asset::<AudioSource>()
+            .init_resource::<AbilityModifiers>()
+            .init_resource::<BeatClock>()
+            .init_resource::<CosmeticRng>()
+            .insert_resource(MouseCoords { x: 0., y: 400. })
+            .insert_resource(GameTextures {
+                player: Handle::default(),
+                enemy: Handle::default(),
+                line: Handle::default(),
+                map: Handle::default(),
+            })
+            .insert_resource(HitboxVisuals { material: Handle::default() });
+
+        let def = AbilityDef::parse_ron(&std::fs::read("assets/abilities/dash.ability.ron").unwrap()).unwrap();
+        let (cooldown, charges) = (def.cooldown, def.charges);
+        let handle = app.world_mut().resource_mut::<Assets<AbilityDef>>().add(def);
+        let dash = handle.id();
+        app.insert_resource(AbilityBook { abilities: vec![handle] });
+
+        // Every charge stored, and the dash button going down on every tick
+        let mut cooldowns = Cooldowns::new();
+        cooldowns.configure(dash, cooldown, charges);
+        for _ in 0..charges {
+            cooldowns.tick(Duration::from_secs_f32(cooldown));
+        }
+        let player = app.world_mut().spawn((Player, Transform::default(), cooldowns)).id();
+        app.insert_resource(ActionState {
+            held: Action::Dash.bit(),
+            pressed: Action::Dash.bit(),
+        });
+        // The dash spreads itself over fixed ticks, so the clock needs to have moved by one
+        app.world_mut().resource_mut::<Time>().advance_by(Duration::from_secs_f64(1. / 64.));
+
+        app.world_mut().run_system_once(ability_system);
+        assert!(app.world().get::<Dash>(player).is_some());
+        assert_eq!(app.world().get::<Cooldowns>(player).unwrap().get_charges(dash).unwrap().stored, charges - 1);
+
+        app.world_mut().run_system_once(ability_system);
+        // Only the first press spent a charge or put out a hitbox
+        assert_eq!(app.world().get::<Cooldowns>(player).unwrap().get_charges(dash).unwrap().stored, charges - 1);
+        let mut hitboxes = app.world_mut().query_filtered::<(), With<Line>>();
+        assert_eq!(hitboxes.iter(app.world()).count(), 1);
     }
 }
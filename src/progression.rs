@@ -0,0 +1,323 @@
+use bevy::prelude::*;
+use rand::seq::index::sample;
+
+use crate::{
+    abilities::{
+        AbilityBook, AbilityDef, AbilityModifiers, AbilityShape, ATTACK_FILE, BLADESTORM_FILE, DASH_FILE, RANGED_FILE,
+    },
+    components::GameState,
+    rng::GameRng,
+    UpgradeChosen,
+};
+
+// Experience needed for the first level, each level after needs this much more
+const XP_FIRST_LEVEL: u32 = 5;
+const XP_PER_LEVEL: u32 = 5;
+// Upgrades offered on every level up
+const DRAFT_SIZE: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpgradeEffect {
+    Cooldown(f32),  // Multiplier
+    Range(f32),     // Multiplier
+    Knockback(f32), // Multiplier
+    Damage(i32),
+    Pierce(usize),
+    Charges(u32),
+}
+
+// A single draftable upgrade, aimed at an ability by the file it loads from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Upgrade {
+    pub ability: &'static str,
+    pub effect: UpgradeEffect,
+}
+
+impl Upgrade {
+    // How the draft shows it, `name` being the ability's display name
+    pub fn label(&self, name: &str) -> String {
+        let percent = |scale: f32| ((scale - 1.0) * 100.0).round().abs();
+
+        match self.effect {
+            UpgradeEffect::Cooldown(scale) => format!("-{}% {} cooldown", percent(scale), name),
+            UpgradeEffect::Range(scale) => format!("{} range +{}%", name, percent(scale)),
+            UpgradeEffect::Knockback(scale) => format!("{} knockback +{}%", name, percent(scale)),
+            UpgradeEffect::Damage(amount) => format!("+{} {} damage", amount, name),
+            UpgradeEffect::Pierce(amount) => format!("+{} {} pierce", amount, name),
+            UpgradeEffect::Charges(amount) => format!("+{} {} charge", amount, name),
+        }
+    }
+
+    // Extra pierce means nothing to abilities that aren't projectiles or already pierce everything
+    fn suits(&self, def: &AbilityDef) -> bool {
+        match self.effect {
+            UpgradeEffect::Pierce(_) => matches!(def.shape, AbilityShape::Projectile { pierce: Some(_), .. }),
+            _ => true,
+        }
+    }
+}
+
+const UPGRADE_POOL: [Upgrade; 11] = [
+    Upgrade { ability: ATTACK_FILE, effect: UpgradeEffect::Range(1.2) },
+    Upgrade { ability: ATTACK_FILE, effect: UpgradeEffect::Damage(1) },
+    Upgrade { ability: ATTACK_FILE, effect: UpgradeEffect::Knockback(1.5) },
+    Upgrade { ability: RANGED_FILE, effect: UpgradeEffect::Pierce(1) },
+    Upgrade { ability: RANGED_FILE, effect: UpgradeEffect::Damage(1) },
+    Upgrade { ability: RANGED_FILE, effect: UpgradeEffect::Cooldown(0.8) },
+    Upgrade { ability: DASH_FILE, effect: UpgradeEffect::Cooldown(0.8) },
+    Upgrade { ability: DASH_FILE, effect: UpgradeEffect::Range(1.25) },
+    Upgrade { ability: DASH_FILE, effect: UpgradeEffect::Charges(1) },
+    Upgrade { ability: BLADESTORM_FILE, effect: UpgradeEffect::Range(1.25) },
+    Upgrade { ability: BLADESTORM_FILE, effect: UpgradeEffect::Cooldown(0.8) },
+];
+
+// Label for a drafted upgrade, falling back to the file if the ability is gone
+pub fn upgrade_label(upgrade: &Upgrade, book: &AbilityBook, defs: &Assets<AbilityDef>) -> String {
+    let def = book.find(upgrade.ability).and_then(|handle| defs.get(handle));
+    upgrade.label(def.map_or(upgrade.ability, |def| &def.name))
+}
+
+#[derive(Resource, Default)]
+pub struct Experience {
+    pub level: u32,
+    pub xp: u32,      // Progress towards the next level
+    pub pending: u32, // Levels gained but not drafted yet
+}
+
+impl Experience {
+    pub fn next_level(&self) -> u32 {
+        XP_FIRST_LEVEL + XP_PER_LEVEL * self.level
+    }
+
+    pub fn add(&mut self, xp: u32) {
+        self.xp += xp;
+        while self.xp >= self.next_level() {
+            self.xp -= self.next_level();
+            self.level += 1;
+            self.pending += 1;
+        }
+    }
+}
+
+// Upgrades on offer during the current level up
+#[derive(Resource, Default)]
+pub struct UpgradeDraft {
+    pub options: Vec<Upgrade>,
+}
+
+pub struct ProgressionPlugin;
+
+impl Plugin for ProgressionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Experience>()
+            .init_resource::<UpgradeDraft>()
+            .add_event::<UpgradeChosen>()
+            .add_systems(OnEnter(GameState::Reset), reset_experience)
+            .add_systems(
+                FixedUpdate,
+                start_level_up.run_if(in_state(GameState::Running)),
+            );
+    }
+}
+
+fn reset_experience(mut experience: ResMut<Experience>, mut draft: ResMut<UpgradeDraft>) {
+    *experience = Experience::default();
+    draft.options.clear();
+}
+
+fn start_level_up(
+    experience: Res<Experience>,
+    mut draft: ResMut<UpgradeDraft>,
+    book: Res<AbilityBook>,
+    defs: Res<Assets<AbilityDef>>,
+    mut rng: ResMut<GameRng>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    // A draft already drawn is waiting for the state change, rolling again would spend gameplay
+    // randomness once per tick until it lands
+    if experience.pending == 0 || !draft.options.is_empty() {
+        return;
+    }
+
+    // Only offer upgrades for abilities the player actually has, and that would change them
+    let pool: Vec<Upgrade> = UPGRADE_POOL
+        .into_iter()
+        .filter(|upgrade| {
+            book.find(upgrade.ability)
+                .and_then(|handle| defs.get(handle))
+                .is_some_and(|def| upgrade.suits(def))
+        })
+        .collect();
+    // Nothing to offer yet (definitions still loading or gone after a hot reload), a draft with
+    // no options would leave the run stuck on the level up screen
+    if pool.is_empty() {
+        return;
+    }
+
+    draft.options = sample(&mut *rng, pool.len(), DRAFT_SIZE.min(pool.len()))
+        .into_iter()
+        .map(|index| pool[index])
+        .collect();
+    next_state.set(GameState::LevelUp);
+}
+
+// Applies the picked upgrade from the draft and uses up one pending level
+pub fn choose_upgrade(
+    index: usize,
+    draft: &mut UpgradeDraft,
+    experience: &mut Experience,
+    book: &AbilityBook,
+    modifiers: &mut AbilityModifiers,
+) -> Option<Upgrade> {
+    let upgrade = *draft.options.get(index)?;
+    let handle = book.find(upgrade.ability)?;
+    let modifier = modifiers.0.entry(handle.id()).or_default();
+
+    match upgrade.effect {
+        UpgradeEffect::Cooldown(scale) => modifier.cooldown *= scale,
+        UpgradeEffect::Range(scale) => modifier.range *= scale,
+        UpgradeEffect::Knockback(scale) => modifier.knockback *= scale,
+        UpgradeEffect::Damage(amount) => modifier.damage += amount,
+        UpgradeEffect::Pierce(amount) => modifier.pierce += amount,
+        UpgradeEffect::Charges(amount) => modifier.charges += amount,
+    }
+
+    experience.pending = experience.pending.saturating_sub(1);
+    draft.options.clear();
+    Some(upgrade)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::state::app::StatesPlugin;
+    use rand::RngCore;
+
+    use super::*;
+    use crate::{abilities::AbilitiesPlugin, sim::wait_for_abilities};
+
+    #[test]
+    fn levels_need_more_xp_each_time() {
+        let mut experience = Experience::default();
+        experience.add(4);
+        assert_eq!(experience.level, 0);
+
+        // 5 for the first level, then 10 for the second
+        experience.add(12);
+        assert_eq!((experience.level, experience.xp, experience.pending), (2, 1, 2));
+    }
+
+    #[test]
+    fn upgrade_labels_read_like_the_effect() {
+        let pierce = Upgrade { ability: RANGED_FILE, effect: UpgradeEffect::Pierce(1) };
+        let cooldown = Upgrade { ability: DASH_FILE, effect: UpgradeEffect::Cooldown(0.8) };
+        let range = Upgrade { ability: BLADESTORM_FILE, effect: UpgradeEffect::Range(1.25) };
+
+        assert_eq!(pierce.label("Ranged"), "+1 Ranged pierce");
+        assert_eq!(cooldown.label("Dash"), "-20% Dash cooldown");
+        assert_eq!(range.label("Bladestorm"), "Bladestorm range +25%");
+    }
+
+    #[test]
+    fn pierce_is_only_offered_to_projectiles_with_a_limit() {
+        let pierce = Upgrade { ability: RANGED_FILE, effect: UpgradeEffect::Pierce(1) };
+        let ranged = AbilityDef::parse_ron(&std::fs::read("assets/abilities/ranged.ability.ron").unwrap()).unwrap();
+        let dash = AbilityDef::parse_ron(&std::fs::read("assets/abilities/dash.ability.ron").unwrap()).unwrap();
+        assert!(!pierce.suits(&dash));
+
+        let with_pierce = |limit: Option<usize>| {
+            let mut def = ranged.clone();
+            let AbilityShape::Projectile { pierce, .. } = &mut def.shape else {
+                panic!("ranged should be a projectile");
+            };
+            *pierce = limit;
+            def
+        };
+        assert!(pierce.suits(&with_pierce(Some(3))));
+        assert!(!pierce.suits(&with_pierce(None)));
+    }
+
+    #[test]
+    fn no_draft_starts_without_abilities_to_upgrade() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin, ProgressionPlugin))
+            .init_asset::<AbilityDef>()
+            .init_resource::<AbilityBook>()
+            .init_resource::<GameRng>()
+            .insert_state(GameState::Running);
+        app.world_mut().resource_mut::<Experience>().add(5);
+
+        app.world_mut().run_schedule(FixedUpdate);
+        app.update();
+
+        assert_eq!(*app.world().resource::<State<GameState>>().get(), GameState::Running);
+        assert!(app.world().resource::<UpgradeDraft>().options.is_empty());
+        assert_eq!(app.world().resource::<Experience>().pending, 1);
+    }
+
+    // A fresh run one level up, after `ticks` fixed ticks in a single frame
+    fn level_up_after(ticks: usize) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin, AbilitiesPlugin, ProgressionPlugin))
+            .init_resource::<GameRng>()
+            .insert_state(GameState::Running);
+        app.update();
+        wait_for_abilities(&mut app);
+        app.world_mut().resource_mut::<Experience>().add(5);
+
+        for _ in 0..ticks {
+            app.world_mut().run_schedule(FixedUpdate);
+        }
+        app
+    }
+
+    #[test]
+    fn a_level_up_draws_a_single_draft() {
+        let mut once = level_up_after(1);
+        let mut thrice = level_up_after(3);
+
+        assert_eq!(
+            once.world().resource::<UpgradeDraft>().options,
+            thrice.world().resource::<UpgradeDraft>().options
+        );
+        // The extra ticks left gameplay randomness where the first one did
+        assert_eq!(
+            once.world_mut().resource_mut::<GameRng>().next_u64(),
+            thrice.world_mut().resource_mut::<GameRng>().next_u64()
+        );
+    }
+
+    #[test]
+    fn pending_levels_draft_upgrades_that_modify_the_ability() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin, AbilitiesPlugin, ProgressionPlugin))
+            .init_resource::<GameRng>()
+            .insert_state(GameState::Running);
+
+        // The pool finds abilities by file, so they have to come through the asset server
+        app.update();
+        wait_for_abilities(&mut app);
+        app.world_mut().resource_mut::<Experience>().add(5);
+
+        app.world_mut().run_schedule(FixedUpdate);
+        app.update();
+
+        assert_eq!(*app.world().resource::<State<GameState>>().get(), GameState::LevelUp);
+        let draft = app.world().resource::<UpgradeDraft>();
+        assert_eq!(draft.options.len(), 3);
+        let upgrade = draft.options[0];
+        let handle = app.world().resource::<AbilityBook>().find(upgrade.ability).unwrap().clone();
+
+        let world = app.world_mut();
+        world.resource_scope(|world, mut draft: Mut<UpgradeDraft>| {
+            world.resource_scope(|world, mut experience: Mut<Experience>| {
+                world.resource_scope(|world, mut modifiers: Mut<AbilityModifiers>| {
+                    let book = world.resource::<AbilityBook>();
+                    choose_upgrade(0, &mut draft, &mut experience, book, &mut modifiers).unwrap();
+                });
+            });
+        });
+
+        assert_eq!(app.world().resource::<Experience>().pending, 0);
+        assert!(app.world().resource::<AbilityModifiers>().0.contains_key(&handle.id()));
+    }
+}
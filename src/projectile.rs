@@ -0,0 +1,260 @@
+use bevy::prelude::*;
+
+use crate::{
+    boomerang_sound, clean_dead,
+    collision::build_broadphase,
+    components::{Boomerang, GameState, HitRegistry, Homing, Projectile},
+    steering::{build_enemy_grid, EnemyGrid},
+};
+
+// Boomerangs are caught once they get this close to their owner
+const CATCH_RADIUS: f32 = 60.;
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                (home_projectiles.after(build_enemy_grid), return_boomerangs, move_projectiles)
+                    .chain()
+                    .before(build_broadphase),
+                break_spent_projectiles.after(clean_dead),
+            )
+                .run_if(in_state(GameState::Running)),
+        );
+    }
+}
+
+fn home_projectiles(
+    time: Res<Time>,
+    grid: Res<EnemyGrid>,
+    mut projectile_query: Query<(&mut Projectile, &Homing, &Transform)>,
+) {
+    for (mut projectile, homing, transform) in projectile_query.iter_mut() {
+        let position = transform.translation.truncate();
+
+        let nearest = grid
+            .0
+            .query(position, homing.range)
+            .map(|(target, _)| *target - position)
+            .filter(|offset| offset.length_squared() <= homing.range * homing.range)
+            .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+
+        let Some(offset) = nearest else {
+            continue;
+        };
+
+        // Turn at most turn_rate radians per second towards the target
+        let max_turn = homing.turn_rate * time.delta_seconds();
+        let turn = projectile.direction.angle_between(offset).clamp(-max_turn, max_turn);
+        projectile.direction = Vec2::from_angle(turn).rotate(projectile.direction);
+    }
+}
+
+fn return_boomerangs(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    owner_query: Query<&Transform, Without<Boomerang>>,
+    mut boomerang_query: Query<(Entity, &mut Projectile, &mut Boomerang, &Transform, Option<&mut HitRegistry>)>,
+) {
+    for (entity, mut projectile, mut boomerang, transform, registry) in boomerang_query.iter_mut() {
+        if !boomerang.returning {
+            if !boomerang.timer.tick(time.delta()).finished() {
+                continue;
+            }
+
+            // Turning around, so everything on the way back can be hit again
+            boomerang.returning = true;
+            if let Some(mut registry) = registry {
+                registry.hit.clear();
+            }
+            boomerang_sound(&asset_server, &mut commands);
+        }
+
+        let Ok(owner) = owner_query.get(boomerang.owner) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+
+        let to_owner = (owner.translation - transform.translation).truncate();
+        if to_owner.length() <= CATCH_RADIUS {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        projectile.direction = to_owner.normalize();
+    }
+}
+
+pub fn move_projectiles(
+    time: Res<Time>,
+    mut projectile_query: Query<(&Projectile, &mut Transform)>,
+) {
+    for (projectile, mut transform) in projectile_query.iter_mut() {
+        transform.translation += (projectile.direction * projectile.speed * time.delta_seconds()).extend(0.);
+        // Face the way it is flying so line colliders and knockback follow it
+        transform.rotation = Quat::from_rotation_z(projectile.direction.to_angle());
+    }
+}
+
+// Projectiles that have used up their pierce break after the hit lands,
+// boomerangs just stop cutting until they turn around
+fn break_spent_projectiles(
+    mut commands: Commands,
+    projectile_query: Query<(Entity, &HitRegistry), (With<Projectile>, Without<Boomerang>)>,
+) {
+    for (entity, registry) in projectile_query.iter() {
+        if registry.is_full() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::FRAC_PI_2, time::Duration};
+
+    use bevy::state::app::StatesPlugin;
+
+    use super::*;
+    use crate::{
+        collision::CollisionPlugin,
+        abilities::AbilityDef,
+        components::{Collider, ColliderShape, CollisionLayer, CollisionLayers, DamageOnHit, Enemy, Health, Score},
+        damage::DamagePlugin,
+        rng::CosmeticRng,
+        steering::SteeringPlugin,
+        CollisionEvent, EnemyKilled, PlayerHit,
+    };
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            StatesPlugin,
+            CollisionPlugin,
+            DamagePlugin,
+            SteeringPlugin,
+            ProjectilePlugin,
+        ))
+        .init_asset::<AudioSource>()
+        .init_resource::<CosmeticRng>()
+        .insert_state(GameState::Running)
+        .insert_resource(Score::new())
+        .add_event::<CollisionEvent>()
+        .add_event::<PlayerHit>()
+        .add_event::<EnemyKilled>()
+        .add_systems(FixedUpdate, clean_dead);
+        app
+    }
+
+    fn tick(app: &mut App, seconds: f32) {
+        app.world_mut().resource_mut::<Time>().advance_by(Duration::from_secs_f32(seconds));
+        app.world_mut().run_schedule(FixedUpdate);
+    }
+
+    fn spawn_enemy(app: &mut App, position: Vec2) -> Entity {
+        app.world_mut()
+            .spawn((
+                Enemy,
+                Health { hp: 10 },
+                Transform::from_translation(position.extend(0.)),
+                Collider::from_shape(ColliderShape::Circle { radius: 20. }),
+                CollisionLayers::new(CollisionLayer::Enemy),
+            ))
+            .id()
+    }
+
+    fn blade(direction: Vec2, speed: f32) -> impl Bundle {
+        (
+            Transform::default(),
+            Collider::from_shape(ColliderShape::Circle { radius: 10. }),
+            CollisionLayers::new(CollisionLayer::PlayerHitbox),
+            Projectile { direction, speed },
+            DamageOnHit {
+                amount: 1,
+                kind: Handle::<AbilityDef>::weak_from_u128(1).id(),
+                knockback: 0.,
+                stun: 0.,
+            },
+        )
+    }
+
+    #[test]
+    fn projectiles_fly_and_face_their_direction() {
+        let mut app = test_app();
+        let projectile = app.world_mut().spawn(blade(Vec2::Y, 100.)).id();
+
+        tick(&mut app, 0.5);
+
+        let transform = app.world().get::<Transform>(projectile).unwrap();
+        assert!(transform.translation.truncate().abs_diff_eq(Vec2::new(0., 50.), 1e-3));
+        assert!((transform.rotation.to_euler(EulerRot::XYZ).2 - FRAC_PI_2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn homing_turns_towards_the_nearest_enemy() {
+        let mut app = test_app();
+        spawn_enemy(&mut app, Vec2::new(0., 300.));
+        let projectile = app
+            .world_mut()
+            .spawn((
+                blade(Vec2::X, 10.),
+                Homing {
+                    turn_rate: 1.,
+                    range: 500.,
+                },
+            ))
+            .id();
+
+        tick(&mut app, 0.5);
+
+        let direction = app.world().get::<Projectile>(projectile).unwrap().direction;
+        assert!((direction.to_angle() - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn pierce_limit_breaks_the_projectile() {
+        let mut app = test_app();
+        let enemies = [10., 20., 30.].map(|x| spawn_enemy(&mut app, Vec2::new(x, 0.)));
+        let projectile = app.world_mut().spawn((blade(Vec2::X, 0.), HitRegistry::with_limit(2))).id();
+
+        tick(&mut app, 0.);
+
+        let hurt = enemies
+            .iter()
+            .filter(|enemy| app.world().get::<Health>(**enemy).unwrap().hp < 10)
+            .count();
+        assert_eq!(hurt, 2);
+        assert!(app.world().get_entity(projectile).is_none());
+    }
+
+    #[test]
+    fn boomerangs_come_back_and_hit_again() {
+        let mut app = test_app();
+        let owner = app.world_mut().spawn(Transform::from_xyz(-200., 0., 0.)).id();
+        let enemy = spawn_enemy(&mut app, Vec2::ZERO);
+        let projectile = app
+            .world_mut()
+            .spawn((
+                blade(Vec2::X, 400.),
+                Boomerang {
+                    owner,
+                    timer: Timer::from_seconds(0.25, TimerMode::Once),
+                    returning: false,
+                },
+                HitRegistry::default(),
+            ))
+            .id();
+
+        for _ in 0..20 {
+            tick(&mut app, 0.05);
+        }
+
+        assert_eq!(app.world().get::<Health>(enemy).unwrap().hp, 8);
+        assert!(app.world().get_entity(projectile).is_none());
+    }
+}
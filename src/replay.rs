@@ -0,0 +1,847 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::{
+    ecs::system::{RunSystemOnce, SystemParam},
+    prelude::*,
+};
+
+use crate::{
+    abilities::{AbilityBook, AbilityDef, AbilityModifiers, AbilityShape},
+    beat::BeatClock,
+    components::{GameState, Health, Score},
+    controls::{Action, ActionInput, ActionState},
+    progression::{choose_upgrade, Experience, UpgradeDraft},
+    rng::{reseed_for_run, GameRng},
+    sim::{headless_app, InputScript},
+    waves::{Formation, WaveDirector, WaveScript},
+    MouseCoords, UpgradeChosen,
+};
+
+const MAGIC: &[u8; 4] = b"GSRP";
+// Bumped whenever the layout below changes, older files are refused rather than misread
+pub const REPLAY_VERSION: u16 = 4;
+const REPLAY_EXTENSION: &str = "replay";
+// Playback speed while fast-forwarding, toggled with Action::FastForward
+const FAST_FORWARD: f64 = 4.0;
+
+// A frame only stores what changed since the one before, these flag which parts follow
+const KEYS_CHANGED: u8 = 1;
+const AIM_CHANGED: u8 = 2;
+const CLOCK_JUMPED: u8 = 4;
+
+// Input for a single fixed tick of a run
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InputFrame {
+    pub held: u32,          // Bit per Action, as in ActionState
+    pub pressed: u32,       // Actions that went down this tick
+    pub aim: Vec2,          // MouseCoords, in world space
+    pub clock: Option<f32>, // Beat clock position, only when it didn't carry on from the last tick
+}
+
+// Picked upgrade `index` right before playing frame `frame`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UpgradePick {
+    pub frame: u32,
+    pub index: u8,
+}
+
+// How the run ended, checked when verifying a playback
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplayOutcome {
+    pub points: u32,
+    pub state_hash: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub content: u64, // GameContent hash, the same input means nothing under other ability or wave tuning
+    pub frames: Vec<InputFrame>,
+    pub picks: Vec<UpgradePick>,
+    pub outcome: ReplayOutcome,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    NotAReplay,
+    UnsupportedVersion(u16),
+    Truncated,
+    DifferentContent { recorded: u64, current: u64 },
+    Diverged { recorded: ReplayOutcome, played: ReplayOutcome },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "could not access replay: {}", error),
+            ReplayError::NotAReplay => write!(f, "not a replay file"),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "replay version {} is not supported (expected {})", version, REPLAY_VERSION)
+            }
+            ReplayError::Truncated => write!(f, "replay ends early"),
+            ReplayError::DifferentContent { recorded, current } => write!(
+                f,
+                "replay was recorded with different ability definitions or wave script (content {:016x}, now {:016x})",
+                recorded, current
+            ),
+            ReplayError::Diverged { recorded, played } => write!(
+                f,
+                "replay diverged: score {} (recorded {}), state {:016x} (recorded {:016x})",
+                played.points, recorded.points, played.state_hash, recorded.state_hash
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Replay {
+    // Little endian throughout: header, frames, upgrade picks, outcome
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.content.to_le_bytes());
+
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        let mut last = InputFrame::default();
+        for frame in self.frames.iter() {
+            let keys_changed = frame.held != last.held || frame.pressed != 0;
+            let aim_changed = frame.aim.x.to_bits() != last.aim.x.to_bits() || frame.aim.y.to_bits() != last.aim.y.to_bits();
+
+            let mut flags = 0;
+            if keys_changed {
+                flags |= KEYS_CHANGED;
+            }
+            if aim_changed {
+                flags |= AIM_CHANGED;
+            }
+            if frame.clock.is_some() {
+                flags |= CLOCK_JUMPED;
+            }
+            bytes.push(flags);
+
+            if keys_changed {
+                bytes.extend_from_slice(&frame.held.to_le_bytes());
+                bytes.extend_from_slice(&frame.pressed.to_le_bytes());
+            }
+            if aim_changed {
+                bytes.extend_from_slice(&frame.aim.x.to_le_bytes());
+                bytes.extend_from_slice(&frame.aim.y.to_le_bytes());
+            }
+            if let Some(clock) = frame.clock {
+                bytes.extend_from_slice(&clock.to_le_bytes());
+            }
+            last = *frame;
+        }
+
+        bytes.extend_from_slice(&(self.picks.len() as u32).to_le_bytes());
+        for pick in self.picks.iter() {
+            bytes.extend_from_slice(&pick.frame.to_le_bytes());
+            bytes.push(pick.index);
+        }
+
+        bytes.extend_from_slice(&self.outcome.points.to_le_bytes());
+        bytes.extend_from_slice(&self.outcome.state_hash.to_le_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = ByteReader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let seed = u64::from_le_bytes(reader.array()?);
+        let content = u64::from_le_bytes(reader.array()?);
+
+        let frame_count = u32::from_le_bytes(reader.array()?);
+        let mut frames = Vec::new();
+        let mut last = InputFrame::default();
+        for _ in 0..frame_count {
+            let flags = reader.byte()?;
+            let mut frame = InputFrame {
+                held: last.held,
+                pressed: 0,
+                aim: last.aim,
+                clock: None,
+            };
+
+            if flags & KEYS_CHANGED != 0 {
+                frame.held = u32::from_le_bytes(reader.array()?);
+                frame.pressed = u32::from_le_bytes(reader.array()?);
+            }
+            if flags & AIM_CHANGED != 0 {
+                frame.aim.x = f32::from_le_bytes(reader.array()?);
+                frame.aim.y = f32::from_le_bytes(reader.array()?);
+            }
+            if flags & CLOCK_JUMPED != 0 {
+                frame.clock = Some(f32::from_le_bytes(reader.array()?));
+            }
+            frames.push(frame);
+            last = frame;
+        }
+
+        let pick_count = u32::from_le_bytes(reader.array()?);
+        let mut picks = Vec::new();
+        for _ in 0..pick_count {
+            let frame = u32::from_le_bytes(reader.array()?);
+            picks.push(UpgradePick { frame, index: reader.byte()? });
+        }
+
+        let outcome = ReplayOutcome {
+            points: u32::from_le_bytes(reader.array()?),
+            state_hash: u64::from_le_bytes(reader.array()?),
+        };
+
+        Ok(Self { seed, content, frames, picks, outcome })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        Self::decode(&fs::read(path).map_err(ReplayError::Io)?)
+    }
+
+    // Hands the replay back only if it was recorded against the content loaded now
+    pub fn check_content(self, current: u64) -> Result<Self, ReplayError> {
+        if self.content != current {
+            return Err(ReplayError::DifferentContent { recorded: self.content, current });
+        }
+        Ok(self)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(ReplayError::Io)?;
+        }
+        fs::write(path, self.encode()).map_err(ReplayError::Io)
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ReplayError> {
+        if self.bytes.len() < count {
+            return Err(ReplayError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ReplayError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+// FNV-1a over explicitly written bytes. Both hashes end up in saved replays, so unlike
+// DefaultHasher the result can't change with the toolchain.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn byte(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn usize(&mut self, value: usize) {
+        self.bytes(&(value as u64).to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.u32(value.to_bits());
+    }
+
+    // Length first, so neighbouring strings can't run into each other
+    fn str(&mut self, value: &str) {
+        self.usize(value.len());
+        self.bytes(value.as_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// Fingerprint of every Transform with Health, equal only if two runs match bit for bit.
+// Entities are combined in any order, so it doesn't depend on how the world stores them.
+pub fn state_hash<'a>(entities: impl IntoIterator<Item = (&'a Transform, &'a Health)>) -> u64 {
+    entities
+        .into_iter()
+        .map(|(transform, health)| {
+            let mut hasher = Fnv1a::new();
+            let floats = transform
+                .translation
+                .to_array()
+                .into_iter()
+                .chain(transform.rotation.to_array())
+                .chain(transform.scale.to_array());
+            for float in floats {
+                hasher.f32(float);
+            }
+            hasher.i32(health.hp);
+            hasher.finish()
+        })
+        .fold(0, u64::wrapping_add)
+}
+
+// The tuning a run is played under, loaded ability definitions and the wave script
+#[derive(SystemParam)]
+pub struct GameContent<'w> {
+    book: Res<'w, AbilityBook>,
+    defs: Res<'w, Assets<AbilityDef>>,
+    director: Res<'w, WaveDirector>,
+}
+
+impl GameContent<'_> {
+    // Fingerprint of every value in the definitions, in cooldown bar order, then the waves
+    pub fn hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        for handle in self.book.abilities.iter() {
+            match self.defs.get(handle) {
+                Some(def) => {
+                    hasher.byte(1);
+                    hash_ability(&mut hasher, def);
+                }
+                None => hasher.byte(0),
+            }
+        }
+        hash_waves(&mut hasher, &self.director.script);
+        hasher.finish()
+    }
+}
+
+fn hash_ability(hasher: &mut Fnv1a, def: &AbilityDef) {
+    hasher.str(&def.name);
+    hasher.byte(def.action as u8);
+    hasher.f32(def.cooldown);
+    hasher.u32(def.charges);
+    hasher.i32(def.damage);
+    hasher.f32(def.knockback);
+    hasher.f32(def.stun);
+    hasher.f32(def.range);
+    hasher.f32(def.lifetime);
+    match def.shape {
+        AbilityShape::Sector { half_angle } => {
+            hasher.byte(0);
+            hasher.f32(half_angle);
+        }
+        AbilityShape::Circle => hasher.byte(1),
+        AbilityShape::Projectile { length, width, speed, boomerang, pierce } => {
+            hasher.byte(2);
+            hasher.f32(length);
+            hasher.f32(width);
+            hasher.f32(speed);
+            hasher.byte(boomerang as u8);
+            match pierce {
+                Some(pierce) => {
+                    hasher.byte(1);
+                    hasher.usize(pierce);
+                }
+                None => hasher.byte(0),
+            }
+        }
+        AbilityShape::Dash { radius } => {
+            hasher.byte(3);
+            hasher.f32(radius);
+        }
+    }
+    hasher.usize(def.sfx.len());
+    for sfx in def.sfx.iter() {
+        hasher.str(sfx);
+    }
+    hasher.f32(def.volume);
+}
+
+fn hash_waves(hasher: &mut Fnv1a, script: &WaveScript) {
+    hasher.usize(script.waves.len());
+    for wave in script.waves.iter() {
+        hasher.f32(wave.start);
+        match wave.end {
+            Some(end) => {
+                hasher.byte(1);
+                hasher.f32(end);
+            }
+            None => hasher.byte(0),
+        }
+        hasher.f32(wave.spawn_interval);
+        hasher.f32(wave.interval_decrease);
+        hasher.f32(wave.min_interval);
+        hasher.f32(wave.ring_radius);
+        hasher.usize(wave.max_alive);
+
+        hasher.usize(wave.mix.len());
+        for (kind, weight) in wave.mix.iter() {
+            hasher.byte(*kind as u8);
+            hasher.f32(*weight);
+        }
+
+        hasher.usize(wave.formations.len());
+        for (formation, weight) in wave.formations.iter() {
+            match *formation {
+                Formation::Single => hasher.byte(0),
+                Formation::Ring { count } => {
+                    hasher.byte(1);
+                    hasher.usize(count);
+                }
+                Formation::Line { count, spacing } => {
+                    hasher.byte(2);
+                    hasher.usize(count);
+                    hasher.f32(spacing);
+                }
+                Formation::Cluster { count, spread } => {
+                    hasher.byte(3);
+                    hasher.usize(count);
+                    hasher.f32(spread);
+                }
+            }
+            hasher.f32(*weight);
+        }
+    }
+}
+
+// Where finished runs are saved, only set for the windowed game so tests never touch the disk
+#[derive(Resource)]
+pub struct ReplayDir(pub PathBuf);
+
+impl ReplayDir {
+    pub fn from_user_data() -> Option<Self> {
+        dirs::data_dir().map(|dir| Self(dir.join("gashadokuro").join("replays")))
+    }
+
+    // File names start with a timestamp, so the last one in order is the newest
+    pub fn latest(&self) -> Option<PathBuf> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&self.0)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == REPLAY_EXTENSION))
+            .collect();
+        paths.sort();
+        paths.pop()
+    }
+}
+
+// Systems that read the keyboard, mouse and gamepads, skipped while a replay supplies the input instead
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GatherInput;
+
+#[derive(Resource, Default)]
+pub struct ReplayRecorder {
+    pub replay: Replay,
+    last_clock: Option<f32>, // Clock as recorded on the previous tick
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    next_frame: usize,
+    next_pick: usize,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            next_frame: 0,
+            next_pick: 0,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next_frame >= self.replay.frames.len()
+    }
+
+    // Applies the upgrade picked right before the next frame, if there is one
+    fn take_due_pick(&mut self) -> Option<usize> {
+        let pick = self.replay.picks.get(self.next_pick)?;
+        if pick.frame as usize != self.next_frame {
+            return None;
+        }
+        self.next_pick += 1;
+        Some(pick.index as usize)
+    }
+}
+
+// Starts a fresh run that plays `replay` back instead of listening to the player
+pub fn start_replay(
+    commands: &mut Commands,
+    replay: Replay,
+    rng: &mut GameRng,
+    next_state: &mut NextState<GameState>,
+) {
+    rng.next_seed = Some(replay.seed);
+    commands.insert_resource(ReplayPlayback::new(replay));
+    next_state.set(GameState::Reset);
+}
+
+// Replay handed over on the command line, started as soon as the abilities are loaded
+#[derive(Resource)]
+pub struct QueuedReplay(pub Replay);
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let playing = resource_exists::<ReplayPlayback>;
+
+        app.init_resource::<ReplayRecorder>()
+            .configure_sets(FixedPreUpdate, GatherInput.run_if(not(playing)))
+            .add_systems(OnEnter(GameState::Reset), start_recording.after(reseed_for_run))
+            .add_systems(
+                FixedPreUpdate,
+                (
+                    record_input.after(GatherInput).run_if(not(playing)),
+                    play_replay_frame.run_if(playing),
+                )
+                    .run_if(in_state(GameState::Running)),
+            )
+            .add_systems(
+                Update,
+                (
+                    play_replay_picks.run_if(in_state(GameState::LevelUp)).run_if(playing),
+                    (fast_forward_replay, end_replay).run_if(playing),
+                    start_queued_replay.run_if(in_state(GameState::Menu)),
+                ),
+            )
+            // After Update, so a pick is always stamped before the frame that follows it is recorded
+            .add_systems(PostUpdate, record_upgrade_picks.run_if(not(playing)))
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                (save_replay.run_if(resource_exists::<ReplayDir>), stop_replay),
+            )
+            .add_systems(
+                OnEnter(GameState::Won),
+                (save_replay.run_if(resource_exists::<ReplayDir>), stop_replay),
+            )
+            .add_systems(
+                OnEnter(GameState::Menu),
+                (save_replay.run_if(resource_exists::<ReplayDir>), stop_replay),
+            );
+    }
+}
+
+fn start_recording(mut recorder: ResMut<ReplayRecorder>, rng: Res<GameRng>, content: GameContent) {
+    *recorder = ReplayRecorder {
+        replay: Replay {
+            seed: rng.seed(),
+            content: content.hash(),
+            ..Default::default()
+        },
+        last_clock: None,
+    };
+}
+
+fn record_input(
+    mut recorder: ResMut<ReplayRecorder>,
+    actions: Res<ActionState>,
+    mouse_coords: Res<MouseCoords>,
+    clock: Res<BeatClock>,
+    time: Res<Time>,
+) {
+    let mut frame = InputFrame {
+        held: actions.held,
+        pressed: actions.pressed,
+        aim: Vec2::new(mouse_coords.x, mouse_coords.y),
+        clock: None,
+    };
+
+    // The clock runs on through pauses and level ups, so note where it is after every gap
+    let expected = recorder.last_clock.map(|last| last + time.delta_seconds());
+    if expected.map(f32::to_bits) != Some(clock.elapsed.to_bits()) {
+        frame.clock = Some(clock.elapsed);
+    }
+    recorder.last_clock = Some(clock.elapsed);
+    recorder.replay.frames.push(frame);
+}
+
+fn record_upgrade_picks(mut recorder: ResMut<ReplayRecorder>, mut chosen_reader: EventReader<UpgradeChosen>) {
+    for chosen in chosen_reader.read() {
+        let frame = recorder.replay.frames.len() as u32;
+        recorder.replay.picks.push(UpgradePick {
+            frame,
+            index: chosen.index as u8,
+        });
+    }
+}
+
+fn save_replay(
+    mut recorder: ResMut<ReplayRecorder>,
+    dir: Res<ReplayDir>,
+    score: Res<Score>,
+    state_query: Query<(&Transform, &Health)>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    if recorder.replay.frames.is_empty() || playback.is_some() {
+        return;
+    }
+
+    let mut replay = std::mem::take(&mut recorder.replay);
+    replay.outcome = ReplayOutcome {
+        points: score.get_points(),
+        state_hash: state_hash(state_query.iter()),
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let path = dir.0.join(format!("{:012}-{}.{}", timestamp, replay.seed, REPLAY_EXTENSION));
+
+    match replay.save(&path) {
+        Ok(()) => info!("Saved replay to {}", path.display()),
+        Err(error) => warn!("Could not save replay to {}: {}", path.display(), error),
+    }
+}
+
+fn play_replay_frame(
+    mut playback: ResMut<ReplayPlayback>,
+    mut actions: ResMut<ActionState>,
+    mut mouse_coords: ResMut<MouseCoords>,
+    mut clock: ResMut<BeatClock>,
+    mut draft: ResMut<UpgradeDraft>,
+    mut experience: ResMut<Experience>,
+    mut modifiers: ResMut<AbilityModifiers>,
+    book: Res<AbilityBook>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    // The level up may not have shown yet if it landed mid-frame, take the pick now anyway
+    if let Some(index) = playback.take_due_pick() {
+        choose_upgrade(index, &mut draft, &mut experience, &book, &mut modifiers);
+        next_state.set(GameState::Running);
+    }
+
+    let Some(frame) = playback.replay.frames.get(playback.next_frame).copied() else {
+        return;
+    };
+    playback.next_frame += 1;
+
+    *actions = ActionState {
+        held: frame.held,
+        pressed: frame.pressed,
+    };
+    mouse_coords.x = frame.aim.x;
+    mouse_coords.y = frame.aim.y;
+    if let Some(elapsed) = frame.clock {
+        clock.seek(elapsed);
+    }
+}
+
+// Takes the recorded pick, or carries on if the run went on for a few more ticks before picking
+fn play_replay_picks(
+    mut playback: ResMut<ReplayPlayback>,
+    mut draft: ResMut<UpgradeDraft>,
+    mut experience: ResMut<Experience>,
+    mut modifiers: ResMut<AbilityModifiers>,
+    book: Res<AbilityBook>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if let Some(index) = playback.take_due_pick() {
+        choose_upgrade(index, &mut draft, &mut experience, &book, &mut modifiers);
+    }
+    next_state.set(GameState::Running);
+}
+
+fn fast_forward_replay(input: ActionInput, mut time: ResMut<Time<Virtual>>) {
+    if input.just_pressed(Action::FastForward) {
+        let speed = if time.relative_speed_f64() > 1.0 { 1.0 } else { FAST_FORWARD };
+        time.set_relative_speed_f64(speed);
+    }
+}
+
+// Once the input runs out the run ends the way it did, or goes back to the menu if it was quit
+fn end_replay(
+    mut commands: Commands,
+    playback: Res<ReplayPlayback>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if !playback.finished() {
+        return;
+    }
+
+    if *state.get() == GameState::Running && matches!(*next_state, NextState::Unchanged) {
+        next_state.set(GameState::Menu);
+    }
+    time.set_relative_speed_f64(1.0);
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+fn stop_replay(mut commands: Commands, mut time: ResMut<Time<Virtual>>) {
+    time.set_relative_speed_f64(1.0);
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+fn start_queued_replay(
+    mut commands: Commands,
+    queued: Option<Res<QueuedReplay>>,
+    content: GameContent,
+    mut rng: ResMut<GameRng>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(queued) = queued else {
+        return;
+    };
+    if !content.book.is_loaded(&content.defs) {
+        return;
+    }
+
+    match queued.0.clone().check_content(content.hash()) {
+        Ok(replay) => start_replay(&mut commands, replay, &mut rng, &mut next_state),
+        Err(error) => error!("Not playing the replay: {}", error),
+    }
+    commands.remove_resource::<QueuedReplay>();
+}
+
+// Plays the replay back headless as fast as possible and checks it ends the way it was recorded
+pub fn verify(replay: &Replay) -> Result<ReplayOutcome, ReplayError> {
+    let mut app = headless_app(replay.seed, InputScript::default());
+    let current = app.world_mut().run_system_once(|content: GameContent| content.hash());
+    if replay.content != current {
+        return Err(ReplayError::DifferentContent { recorded: replay.content, current });
+    }
+    app.insert_resource(ReplayPlayback::new(replay.clone()));
+
+    // Level ups and the end of the run each take an extra update on top of the frames
+    let limit = replay.frames.len() + 4 * replay.picks.len() + 8;
+    for _ in 0..limit {
+        if !app.world().contains_resource::<ReplayPlayback>() {
+            break;
+        }
+        app.update();
+    }
+
+    let mut state_query = app.world_mut().query::<(&Transform, &Health)>();
+    let outcome = ReplayOutcome {
+        points: app.world().resource::<Score>().get_points(),
+        state_hash: state_hash(state_query.iter(app.world())),
+    };
+
+    if outcome == replay.outcome {
+        Ok(outcome)
+    } else {
+        Err(ReplayError::Diverged { recorded: replay.outcome, played: outcome })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{run_ticks, ScriptedInput, TICKS_PER_SECOND};
+
+    fn sample_replay() -> Replay {
+        Replay {
+            seed: 99,
+            content: 42,
+            frames: vec![
+                InputFrame { held: 1, pressed: 1, aim: Vec2::new(3., 4.), clock: Some(12.5) },
+                InputFrame { held: 1, pressed: 0, aim: Vec2::new(3., 4.), clock: None },
+                InputFrame { held: 3, pressed: 2, aim: Vec2::new(-1., 4.), clock: None },
+            ],
+            picks: vec![UpgradePick { frame: 2, index: 1 }],
+            outcome: ReplayOutcome { points: 10, state_hash: 1234 },
+        }
+    }
+
+    #[test]
+    fn replays_round_trip_and_unchanged_ticks_take_one_byte() {
+        let replay = sample_replay();
+        let bytes = replay.encode();
+        assert_eq!(Replay::decode(&bytes).unwrap(), replay);
+
+        let mut longer = replay.clone();
+        longer.frames.push(InputFrame { held: 3, pressed: 0, aim: Vec2::new(-1., 4.), clock: None });
+        assert_eq!(longer.encode().len(), bytes.len() + 1);
+    }
+
+    #[test]
+    fn other_versions_and_short_files_are_refused() {
+        let mut bytes = sample_replay().encode();
+        bytes[4] = 99;
+        assert!(matches!(Replay::decode(&bytes), Err(ReplayError::UnsupportedVersion(99))));
+
+        let bytes = sample_replay().encode();
+        assert!(matches!(Replay::decode(&bytes[..bytes.len() - 3]), Err(ReplayError::Truncated)));
+        assert!(matches!(Replay::decode(b"nope"), Err(ReplayError::NotAReplay)));
+    }
+
+    #[test]
+    fn hashes_are_pinned_to_fnv1a() {
+        // Published FNV-1a 64 test vectors, saved replays depend on these never changing
+        let mut hasher = Fnv1a::new();
+        assert_eq!(hasher.finish(), 0xcbf2_9ce4_8422_2325);
+        hasher.bytes(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+
+        let mut hasher = Fnv1a::new();
+        hasher.bytes(b"foobar");
+        assert_eq!(hasher.finish(), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn recorded_runs_play_back_to_the_same_state() {
+        let mut script = InputScript::default();
+        for second in 0..20 {
+            let tick = second * TICKS_PER_SECOND;
+            script
+                .hold(tick..tick + 50, [KeyCode::KeyA, KeyCode::KeyS][second as usize % 2])
+                .at(tick, ScriptedInput::Aim(Vec2::from_angle(second as f32 * 2.) * 250.))
+                .tap(tick + 3, KeyCode::KeyQ)
+                .tap(tick + 20, KeyCode::KeyT)
+                .tap(tick + 40, KeyCode::Digit2);
+        }
+
+        let mut app = headless_app(5, script);
+        run_ticks(&mut app, 20 * TICKS_PER_SECOND);
+
+        let mut state_query = app.world_mut().query::<(&Transform, &Health)>();
+        let outcome = ReplayOutcome {
+            points: app.world().resource::<Score>().get_points(),
+            state_hash: state_hash(state_query.iter(app.world())),
+        };
+        let mut replay = std::mem::take(&mut app.world_mut().resource_mut::<ReplayRecorder>().replay);
+        replay.outcome = outcome;
+
+        assert_eq!(replay.seed, 5);
+        assert!(outcome.points > 0, "the scripted run should kill something");
+        let replay = Replay::decode(&replay.encode()).unwrap();
+        assert_eq!(verify(&replay).unwrap(), outcome);
+
+        // Retuned content refuses the replay instead of diverging from it
+        let retuned = Replay { content: replay.content ^ 1, ..replay };
+        assert!(matches!(verify(&retuned), Err(ReplayError::DifferentContent { .. })));
+        assert!(matches!(retuned.check_content(replay.content), Err(ReplayError::DifferentContent { .. })));
+    }
+}
@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+use crate::components::GameState;
+
+// The only source of randomness for gameplay, reseeded at the start of every run so a seed
+// and an input log are enough to play a run back exactly
+#[derive(Resource)]
+pub struct GameRng {
+    seed: u64,
+    rng: StdRng,
+    pub next_seed: Option<u64>, // Seed for the next run, a fresh random one when None
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            next_seed: None,
+        }
+    }
+
+    // Seed the current run started from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+// Randomness that never touches the simulation, like which hit sound plays. Seeded from the OS
+// so sound variety doesn't shift the gameplay rolls and break replays.
+#[derive(Resource)]
+pub struct CosmeticRng(pub StdRng);
+
+impl Default for CosmeticRng {
+    fn default() -> Self {
+        Self(StdRng::from_entropy())
+    }
+}
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameRng>()
+            .init_resource::<CosmeticRng>()
+            .add_systems(OnEnter(GameState::Reset), reseed_for_run);
+    }
+}
+
+pub fn reseed_for_run(mut rng: ResMut<GameRng>) {
+    let seed = rng.next_seed.take().unwrap_or_else(rand::random);
+    rng.reseed(seed);
+    info!("Starting run with seed {}", seed);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn same_seed_same_rolls() {
+        let mut first = GameRng::new(42);
+        let mut second = GameRng::new(7);
+        second.reseed(42);
+
+        let rolls: Vec<u32> = (0..8).map(|_| first.gen_range(0..1000)).collect();
+        let replayed: Vec<u32> = (0..8).map(|_| second.gen_range(0..1000)).collect();
+        assert_eq!(rolls, replayed);
+        assert_eq!(second.seed(), 42);
+    }
+}
@@ -0,0 +1,276 @@
+// Runs the whole game without a window, renderer or audio output, driven by scripted input.
+// Used by the tests below and by `cargo run --bin sim`.
+use std::{fmt, thread, time::Duration};
+
+use bevy::{
+    input::gamepad::{GamepadAxis, GamepadButton, Gamepads},
+    state::app::StatesPlugin,
+    time::TimeUpdateStrategy,
+};
+use bevy::prelude::*;
+
+use crate::{
+    abilities::{AbilityBook, AbilityDef},
+    components::{Bigfoot, GameState, Health, Player, Score},
+    controls::latch_presses,
+    rng::GameRng,
+    systems::SkipBigfoot,
+    GamePlugins, MouseCoords,
+};
+
+// One default fixed timestep, every update of a headless app advances exactly one tick
+pub const TICK: Duration = Duration::from_micros(15625);
+pub const TICKS_PER_SECOND: u32 = 64;
+// Ability definitions load on a worker thread, give up on them after this long
+const ASSET_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScriptedInput {
+    Press(KeyCode),
+    Release(KeyCode),
+    Aim(Vec2), // Moves the cursor to this world position
+}
+
+// Input fed to the game tick by tick instead of coming from a keyboard and mouse
+#[derive(Resource, Default)]
+pub struct InputScript {
+    steps: Vec<(u32, ScriptedInput)>,
+    tick: u32, // Ticks played so far
+}
+
+impl InputScript {
+    pub fn at(&mut self, tick: u32, input: ScriptedInput) -> &mut Self {
+        self.steps.push((tick, input));
+        self
+    }
+
+    // Presses the key for a single tick
+    pub fn tap(&mut self, tick: u32, key: KeyCode) -> &mut Self {
+        self.at(tick, ScriptedInput::Press(key))
+            .at(tick + 1, ScriptedInput::Release(key))
+    }
+
+    // Holds the key down over `ticks`
+    pub fn hold(&mut self, ticks: std::ops::Range<u32>, key: KeyCode) -> &mut Self {
+        self.at(ticks.start, ScriptedInput::Press(key))
+            .at(ticks.end, ScriptedInput::Release(key))
+    }
+}
+
+pub struct ScriptedInputPlugin;
+
+impl Plugin for ScriptedInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<ButtonInput<MouseButton>>()
+            .init_resource::<ButtonInput<GamepadButton>>()
+            .init_resource::<Axis<GamepadAxis>>()
+            .init_resource::<Gamepads>()
+            .init_resource::<InputScript>()
+            .add_systems(PreUpdate, play_input_script.before(latch_presses));
+    }
+}
+
+// Stands in for InputPlugin, so just_pressed only lasts the tick the key went down. Mouse and
+// gamepads are left idle.
+fn play_input_script(
+    mut script: ResMut<InputScript>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut mouse_coords: ResMut<MouseCoords>,
+) {
+    keys.clear();
+
+    let tick = script.tick;
+    for (_, input) in script.steps.iter().filter(|(at, _)| *at == tick) {
+        match *input {
+            ScriptedInput::Press(key) => keys.press(key),
+            ScriptedInput::Release(key) => keys.release(key),
+            ScriptedInput::Aim(position) => {
+                mouse_coords.x = position.x;
+                mouse_coords.y = position.y;
+            }
+        }
+    }
+    script.tick += 1;
+}
+
+// The game with every asset type it loads registered, waiting on a fresh run from `seed`
+// once abilities are in
+pub fn headless_app(seed: u64, script: InputScript) -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin, GamePlugins, ScriptedInputPlugin))
+        .init_asset::<Image>()
+        .init_asset::<Mesh>()
+        .init_asset::<ColorMaterial>()
+        .init_asset::<AudioSource>()
+        .init_asset::<Font>()
+        .insert_resource(script)
+        // Time stands still while loading so the run starts from the same clock every time
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO));
+
+    // Startup lands in the menu, the script only starts playing once the run is under way
+    app.update();
+    wait_for_abilities(&mut app);
+    // Reset takes one update, then hands over to Running on the next
+    app.world_mut().resource_mut::<GameRng>().next_seed = Some(seed);
+    app.world_mut().resource_mut::<NextState<GameState>>().set(GameState::Reset);
+    app.update();
+    app.update();
+    app.world_mut().resource_mut::<InputScript>().tick = 0;
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(TICK));
+
+    app
+}
+
+// Updates the app until every ability definition is in, failing the test if they never arrive
+pub fn wait_for_abilities(app: &mut App) {
+    let mut waited = Duration::ZERO;
+
+    loop {
+        let book = app.world().resource::<AbilityBook>();
+        let defs = app.world().resource::<Assets<AbilityDef>>();
+        if book.is_loaded(defs) {
+            return;
+        }
+
+        assert!(waited < ASSET_TIMEOUT, "ability definitions did not load");
+        thread::sleep(Duration::from_millis(5));
+        waited += Duration::from_millis(5);
+        app.update();
+    }
+}
+
+// Takes Bigfoot out of the run and keeps it from coming back after a level up, for checks
+// that are about the horde rather than the boss
+pub fn without_bigfoot(app: &mut App) {
+    app.insert_resource(SkipBigfoot);
+    let mut bigfoot_query = app.world_mut().query_filtered::<Entity, With<Bigfoot>>();
+    let bigfoot: Vec<Entity> = bigfoot_query.iter(app.world()).collect();
+    for entity in bigfoot {
+        app.world_mut().despawn(entity);
+    }
+}
+
+pub fn run_ticks(app: &mut App, ticks: u32) {
+    for _ in 0..ticks {
+        app.update();
+    }
+}
+
+// The numbers a balance check cares about
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimReport {
+    pub state: GameState,
+    pub health: Option<i32>, // None once the player is gone
+    pub points: u32,
+    pub kills: u32,
+}
+
+impl SimReport {
+    pub fn of(app: &mut App) -> Self {
+        let mut player_query = app.world_mut().query_filtered::<&Health, With<Player>>();
+        let health = player_query.get_single(app.world()).ok().map(|health| health.hp);
+        let score = app.world().resource::<Score>();
+
+        Self {
+            state: *app.world().resource::<State<GameState>>().get(),
+            health,
+            points: score.get_points(),
+            kills: score.get_enemies_killed(),
+        }
+    }
+}
+
+impl fmt::Display for SimReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let health = self.health.map_or("-".to_string(), |hp| hp.to_string());
+        write!(f, "{:?} health {} score {} kills {}", self.state, health, self.points, self.kills)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{components::GameTimer, replay::state_hash, PLAYER_MAX_HEALTH};
+
+    #[test]
+    fn runs_start_with_a_fresh_player() {
+        let mut app = headless_app(0, InputScript::default());
+
+        let report = SimReport::of(&mut app);
+        assert_eq!(report.state, GameState::Running);
+        assert_eq!(report.health, Some(PLAYER_MAX_HEALTH));
+        assert_eq!(report.points, 0);
+    }
+
+    #[test]
+    fn standing_still_gets_the_player_hurt() {
+        let mut app = headless_app(0, InputScript::default());
+        run_ticks(&mut app, 45 * TICKS_PER_SECOND);
+
+        let report = SimReport::of(&mut app);
+        assert!(report.health.is_none_or(|hp| hp < PLAYER_MAX_HEALTH), "{}", report);
+    }
+
+    #[test]
+    fn swinging_at_the_horde_scores() {
+        let mut script = InputScript::default();
+        for second in 0..45 {
+            let tick = second * TICKS_PER_SECOND;
+            script.tap(tick, KeyCode::KeyT).tap(tick + 32, KeyCode::Digit1);
+        }
+        let mut app = headless_app(0, script);
+        // Bigfoot's stomp one-shots a player who stands their ground, this is about the horde
+        without_bigfoot(&mut app);
+        run_ticks(&mut app, 45 * TICKS_PER_SECOND);
+
+        let report = SimReport::of(&mut app);
+        assert!(report.points > 0, "{}", report);
+    }
+
+    #[test]
+    fn same_seed_and_input_replay_bit_for_bit() {
+        let script = || {
+            let mut script = InputScript::default();
+            for second in 0..30 {
+                let tick = second * TICKS_PER_SECOND;
+                script
+                    .hold(tick..tick + 40, [KeyCode::KeyW, KeyCode::KeyD][second as usize % 2])
+                    .at(tick, ScriptedInput::Aim(Vec2::from_angle(second as f32) * 300.))
+                    .tap(tick, KeyCode::KeyQ)
+                    .tap(tick + 16, KeyCode::KeyE)
+                    .tap(tick + 32, KeyCode::Digit1);
+            }
+            script
+        };
+
+        let mut runs: Vec<App> = [7, 7, 8].into_iter().map(|seed| headless_app(seed, script())).collect();
+        for _ in 0..3 {
+            let hashes: Vec<u64> = runs
+                .iter_mut()
+                .map(|app| {
+                    run_ticks(app, 10 * TICKS_PER_SECOND);
+                    let mut state_query = app.world_mut().query::<(&Transform, &Health)>();
+                    state_hash(state_query.iter(app.world()))
+                })
+                .collect();
+
+            assert_eq!(hashes[0], hashes[1]);
+            assert_ne!(hashes[0], hashes[2]);
+        }
+    }
+
+    #[test]
+    fn escape_pauses_the_run() {
+        let mut script = InputScript::default();
+        script.tap(10, KeyCode::Escape);
+        let mut app = headless_app(0, script);
+        run_ticks(&mut app, 20);
+
+        let paused_at = app.world().resource::<GameTimer>().0;
+        run_ticks(&mut app, TICKS_PER_SECOND);
+
+        assert_eq!(SimReport::of(&mut app).state, GameState::Paused);
+        assert_eq!(app.world().resource::<GameTimer>().0, paused_at);
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+// Uniform grid bucketing items by position, rebuilt from scratch whenever the world moves
+pub struct SpatialHash<T> {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<(Vec2, T)>>,
+}
+
+impl<T: Copy> SpatialHash<T> {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        // Keep the allocations of cells that were used last tick, drop the ones the world has moved away from
+        self.cells.retain(|_, cell| !cell.is_empty());
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    pub fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    pub fn insert(&mut self, position: Vec2, item: T) {
+        let cell = self.cell(position);
+        self.cells.entry(cell).or_default().push((position, item));
+    }
+
+    // Every item in the cells overlapping the square around `center`, callers filter by exact distance
+    pub fn query(&self, center: Vec2, radius: f32) -> impl Iterator<Item = &(Vec2, T)> {
+        let min = self.cell(center - Vec2::splat(radius));
+        let max = self.cell(center + Vec2::splat(radius));
+
+        (min.x..=max.x)
+            .flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_finds_items_in_neighbouring_cells_only() {
+        let mut grid = SpatialHash::new(10.);
+        grid.insert(Vec2::new(1., 1.), 1);
+        grid.insert(Vec2::new(-1., -1.), 2);
+        grid.insert(Vec2::new(15., 5.), 3);
+        grid.insert(Vec2::new(100., 100.), 4);
+
+        let mut found: Vec<i32> = grid.query(Vec2::ZERO, 5.).map(|(_, item)| *item).collect();
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+
+        let mut found: Vec<i32> = grid.query(Vec2::new(10., 0.), 5.).map(|(_, item)| *item).collect();
+        found.sort();
+        assert_eq!(found, vec![1, 3]);
+    }
+
+    #[test]
+    fn clear_empties_the_grid() {
+        let mut grid = SpatialHash::new(10.);
+        grid.insert(Vec2::ZERO, 1);
+        grid.clear();
+
+        assert_eq!(grid.query(Vec2::ZERO, 50.).count(), 0);
+    }
+
+    #[test]
+    fn cells_left_empty_for_a_tick_are_dropped() {
+        let mut grid = SpatialHash::new(10.);
+        for step in 0..100 {
+            grid.clear();
+            grid.insert(Vec2::new(step as f32 * 10., 0.), step);
+        }
+
+        // Only the cell filled this tick and the one filled the tick before are kept
+        assert_eq!(grid.cells.len(), 2);
+    }
+}
@@ -0,0 +1,197 @@
+use bevy::prelude::*;
+
+use crate::{
+    components::{Enemy, GameState, Player, Steering, SteeringBehaviour, Stunned, Velocity},
+    spatial::SpatialHash,
+};
+
+// Enemies closer than this push each other apart
+const SEPARATION_RADIUS: f32 = 45.;
+// Enemies closer than this count as part of the same pack for cohesion
+const NEIGHBOUR_RADIUS: f32 = 120.;
+// Cap on neighbours looked at per enemy so dense hordes stay cheap
+const MAX_NEIGHBOURS: usize = 16;
+// How quickly velocity turns towards the desired heading each tick, 1 is instant
+const STEERING_RESPONSE: f32 = 0.2;
+// Flankers stop swinging wide once they are this close
+const FLANK_DISTANCE: f32 = 250.;
+
+pub struct SteeringPlugin;
+
+impl Plugin for SteeringPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(EnemyGrid(SpatialHash::new(NEIGHBOUR_RADIUS)))
+            .add_systems(
+                FixedUpdate,
+                (build_enemy_grid, player_tracking_system)
+                    .chain()
+                    .run_if(in_state(GameState::Running)),
+            );
+    }
+}
+
+// Enemy positions bucketed for neighbour lookups, rebuilt every fixed tick
+#[derive(Resource)]
+pub struct EnemyGrid(pub SpatialHash<Entity>);
+
+pub fn build_enemy_grid(
+    mut grid: ResMut<EnemyGrid>,
+    enemy_query: Query<(Entity, &Transform), With<Enemy>>,
+) {
+    grid.0.clear();
+
+    for (entity, transform) in enemy_query.iter() {
+        grid.0.insert(transform.translation.truncate(), entity);
+    }
+}
+
+pub fn player_tracking_system(
+    grid: Res<EnemyGrid>,
+    player_query: Query<&Transform, With<Player>>,
+    mut enemy_query: Query<(Entity, &mut Velocity, &Transform, &Steering), (With<Enemy>, Without<Stunned>)>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let player_position = player_transform.translation.truncate();
+
+    for (entity, mut velocity, transform, steering) in enemy_query.iter_mut() {
+        let position = transform.translation.truncate();
+
+        let mut separation = Vec2::ZERO;
+        let mut pack_center = Vec2::ZERO;
+        let mut pack_size = 0;
+
+        for (other_position, other) in grid.0.query(position, NEIGHBOUR_RADIUS) {
+            if *other == entity {
+                continue;
+            }
+
+            let offset = position - *other_position;
+            let distance = offset.length();
+            if distance >= NEIGHBOUR_RADIUS {
+                continue;
+            }
+
+            if distance < SEPARATION_RADIUS {
+                // Stacked enemies get nudged apart in a stable direction instead of NaN
+                let away = offset.try_normalize().unwrap_or_else(|| Vec2::from_angle(steering.bias));
+                separation += away * (1.0 - distance / SEPARATION_RADIUS);
+            }
+
+            pack_center += *other_position;
+            pack_size += 1;
+
+            if pack_size >= MAX_NEIGHBOURS {
+                break;
+            }
+        }
+
+        let cohesion = if pack_size > 0 {
+            (pack_center / pack_size as f32 - position).normalize_or_zero()
+        } else {
+            Vec2::ZERO
+        };
+
+        let seek = behaviour_heading(steering.behaviour, steering.bias, player_position - position);
+
+        let desired = (seek * steering.seek + separation * steering.separation + cohesion * steering.cohesion)
+            .clamp_length_max(1.0);
+
+        let current = Vec2::new(velocity.x, velocity.y);
+        let heading = current.lerp(desired, STEERING_RESPONSE);
+
+        velocity.x = heading.x;
+        velocity.y = heading.y;
+    }
+}
+
+// Where an enemy wants to go relative to the player before crowd forces are applied
+fn behaviour_heading(behaviour: SteeringBehaviour, bias: f32, to_player: Vec2) -> Vec2 {
+    let distance = to_player.length();
+    let direction = to_player.normalize_or_zero();
+
+    match behaviour {
+        SteeringBehaviour::Seek => direction,
+        SteeringBehaviour::Flank { angle } => {
+            // Half the flankers go left, half go right, and they straighten out on approach
+            let side = if bias >= 0. { 1.0 } else { -1.0 };
+            let swing = angle * side * (distance / FLANK_DISTANCE).min(1.0);
+            Vec2::from_angle(swing).rotate(direction)
+        }
+        SteeringBehaviour::Orbit { radius } => {
+            // Circle the player, drifting back towards the orbit radius
+            let tangent = direction.perp();
+            let correction = (distance - radius) / radius;
+            (tangent + direction * correction.clamp(-1.0, 1.0) * 2.0).normalize_or_zero()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(EnemyGrid(SpatialHash::new(NEIGHBOUR_RADIUS)))
+            .add_systems(Update, (build_enemy_grid, player_tracking_system).chain());
+        app.world_mut().spawn((Player, Transform::from_xyz(1000., 0., 0.)));
+        app
+    }
+
+    fn spawn_enemy(app: &mut App, position: Vec2, behaviour: SteeringBehaviour, bias: f32) -> Entity {
+        app.world_mut()
+            .spawn((
+                Enemy,
+                Transform::from_translation(position.extend(0.)),
+                Velocity { x: 0., y: 0. },
+                Steering {
+                    seek: 1.0,
+                    separation: 1.5,
+                    cohesion: 0.1,
+                    behaviour,
+                    bias,
+                },
+            ))
+            .id()
+    }
+
+    fn heading(app: &App, entity: Entity) -> Vec2 {
+        let velocity = app.world().get::<Velocity>(entity).unwrap();
+        Vec2::new(velocity.x, velocity.y)
+    }
+
+    #[test]
+    fn lone_enemy_seeks_the_player() {
+        let mut app = test_app();
+        let enemy = spawn_enemy(&mut app, Vec2::ZERO, SteeringBehaviour::Seek, 0.);
+
+        for _ in 0..50 {
+            app.update();
+        }
+
+        assert!(heading(&app, enemy).angle_between(Vec2::X).abs() < 1e-3);
+    }
+
+    #[test]
+    fn stacked_enemies_push_apart() {
+        let mut app = test_app();
+        let a = spawn_enemy(&mut app, Vec2::ZERO, SteeringBehaviour::Seek, 0.5);
+        let b = spawn_enemy(&mut app, Vec2::ZERO, SteeringBehaviour::Seek, -2.);
+
+        app.update();
+
+        let (a, b) = (heading(&app, a), heading(&app, b));
+        assert!(a.is_finite() && b.is_finite());
+        assert!(a.distance(b) > 0.1);
+    }
+
+    #[test]
+    fn orbiters_circle_at_their_radius() {
+        let heading = behaviour_heading(SteeringBehaviour::Orbit { radius: 300. }, 0., Vec2::new(300., 0.));
+
+        assert!(heading.dot(Vec2::X).abs() < 1e-3);
+    }
+}
@@ -1,12 +1,13 @@
 use bevy::audio::{PlaybackMode, Volume};
 use bevy::prelude::*;
+use crate::abilities::AbilityDef;
 use crate::components::{
-    Ability, Bigfoot, BigfootState, Collider, CooldownUi, Cooldowns, GameState, GameTimer,
-    GameTimerText, Health, HealthText, Invulnerability, Lifetime, Map, MapGrid, Player, Points,
+    Bigfoot, BigfootState, Collider, CollisionLayer, CollisionLayers, CooldownUi, Cooldowns, Enemy, EnemyKind, GameState, GameTimer,
+    GameTimerText, Health, HealthText, Invulnerability, LastHitBy, Lifetime, Map, MapGrid, Player,
     Resettable, Score, ScoreText,
 };
 use crate::{
-    EnemySpawnRate, GameTextures, MouseCoords, ENEMY_SPRITE, LINE_SPRITE, MAP_SPIRITE,
+    EnemyKilled, PlayerHit, GameTextures, MouseCoords, ENEMY_SPRITE, LINE_SPRITE, MAP_SPIRITE,
     PLAYER_SPRITE,
 };
 use rand::Rng;
@@ -65,15 +66,23 @@ pub fn clean_dead(
     mut commands: Commands,
     mut next_state: ResMut<NextState<GameState>>,
     asset_server: Res<AssetServer>,
-    mut query: Query<(Entity, &Health, Option<&Player>)>,
+    mut kill_events: EventWriter<EnemyKilled>,
+    mut query: Query<(Entity, &Health, Option<&Player>, Option<&Enemy>, Option<&EnemyKind>, Option<&LastHitBy>, Option<&Transform>)>,
 ) {
     let mut trigger_game_over = false;
 
-    for (entity, health, maybe_player) in query.iter_mut() {
+    for (entity, health, maybe_player, maybe_enemy, kind, last_hit, transform) in query.iter_mut() {
         if health.hp <= 0 {
             if maybe_player.is_some() {
                 trigger_game_over = true;
             }
+            if maybe_enemy.is_some() {
+                kill_events.send(EnemyKilled {
+                    kind: kind.copied().unwrap_or_default(),
+                    position: transform.map(|t| t.translation.truncate()).unwrap_or_default(),
+                    ability: last_hit.map(|hit| hit.0),
+                });
+            }
             commands.entity(entity).despawn_recursive();
         }
     }
@@ -84,30 +93,50 @@ pub fn clean_dead(
     }
 }
 
-//pub fn enemy_killed(score: &mut ResMut<Score>, mut player: &mut Player, cooldowns_query: &mut Query<&mut Cooldowns>,) {
-//    score.increment();
-//    player.heal(1);
-//    println!("Score: {}", score.get_enemies_killed());
-//     // Apply cooldown reduction to all abilities
-//     // Apply cooldown reduction to all abilities
-//     for mut cooldowns in cooldowns_query.iter_mut() {
-//        for timer in cooldowns.cooldowns.values_mut() {
-//            // Calculate the new elapsed time, ensuring it doesn't go below zero
-//            let elapsed_time = timer.elapsed_secs() + 0.05;
-//
-//            // Manually set the timer's tick to the new elapsed time
-//            timer.set_elapsed(Duration::from_secs_f32(elapsed_time.min(timer.duration().as_secs_f32())));
-//        }
-//    }
-//}
+// Every source of player damage goes through PlayerHit so invulnerability is handled in one place
+pub fn apply_player_damage(
+    mut commands: Commands,
+    mut player_hits: EventReader<PlayerHit>,
+    mut player_query: Query<(Entity, &mut Health, &mut Transform, Option<&Invulnerability>), With<Player>>,
+) {
+    if let Ok((entity, mut health, mut transform, invulnerability)) = player_query.get_single_mut() {
+        let mut invulnerable = invulnerability.is_some();
+
+        for hit in player_hits.read() {
+            if invulnerable {
+                continue;
+            }
+
+            health.take_damage(hit.amount);
+            transform.translation += hit.knockback.extend(0.);
+
+            if hit.invulnerability > 0.0 {
+                commands.entity(entity).insert(Invulnerability {
+                    timer: Timer::from_seconds(hit.invulnerability, TimerMode::Once),
+                });
+                invulnerable = true;
+            }
+        }
+    } else {
+        player_hits.clear();
+    }
+}
+
+const BIGFOOT_STOMP_RADIUS: f32 = 175.0;
+const BIGFOOT_STOMP_DAMAGE: i32 = 500;
+
+// Keeps Bigfoot out of the run, so sim tests can check the horde on its own
+#[derive(Resource)]
+pub struct SkipBigfoot;
 
 pub fn spawn_bigfoot(
     mut commands: Commands,
     player_query: Query<&Transform, With<Player>>,
     asset_server: Res<AssetServer>,
     existing_bigfoot: Query<Entity, With<Bigfoot>>,
+    skip: Option<Res<SkipBigfoot>>,
 ) {
-    if existing_bigfoot.iter().next().is_some() {
+    if skip.is_some() || existing_bigfoot.iter().next().is_some() {
         return;
     }
 
@@ -125,16 +154,14 @@ pub fn spawn_bigfoot(
                     },
                     ..Default::default()
                 },
-                Bigfoot {
-                    timer: Timer::from_seconds(2.5, TimerMode::Once),
-                    state: BigfootState::Invulnerable,
-                    health: 5,
-                    x: player_position.x,
-                    y: player_position.y,
-                    airTexture: asset_server.load("foot.png"),
-                    groundTexture: asset_server.load("foot_ground.png"),
-                },
+                Bigfoot::new(
+                    player_position.x,
+                    player_position.y,
+                    asset_server.load("foot.png"),
+                    asset_server.load("foot_ground.png"),
+                ),
                 Collider::new(Vec2::new(256.0, 256.0)),
+                CollisionLayers::new(CollisionLayer::Boss),
                 Resettable,
             ));
     }
@@ -143,14 +170,17 @@ pub fn spawn_bigfoot(
 
 pub fn update_bigfoot(
     mut query: Query<(Entity, &mut Bigfoot, &mut Sprite, &mut Transform, &mut Handle<Image>), Without<Player>>,
-    mut player_query: Query<(&mut Transform, Option<&mut Invulnerability>), With<Player>>,
+    player_query: Query<&Transform, With<Player>>,
     time: Res<Time>,
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut player_hits: EventWriter<PlayerHit>,
 ) {
-    for (_entity, mut bigfoot, mut sprite, mut transform, mut texture) in query.iter_mut() {
-        // Update Bigfoot's timer
+    for (entity, mut bigfoot, mut sprite, mut transform, mut texture) in query.iter_mut() {
+        // Update Bigfoot's timers
         bigfoot.timer.tick(time.delta());
+        bigfoot.hit_timer.tick(time.delta());
 
         if bigfoot.timer.just_finished() {
             match bigfoot.state {
@@ -168,32 +198,20 @@ pub fn update_bigfoot(
                     cycle_texture(&mut texture, &bigfoot);
                     stomp_sound(&asset_server, &mut commands);
 
-                    if let Ok((mut player_transform, _invulnerability_option)) = player_query.get_single_mut() {
-                        let player_position = Vec3 { x: player_transform.translation.x, y: player_transform.translation.y, z: 1.0 };
-                        let bigfoot_position = Vec3 { x: bigfoot.x, y: bigfoot.y, z: 1.0 };
+                    if let Ok(player_transform) = player_query.get_single() {
+                        let player_position = player_transform.translation.truncate();
+                        let bigfoot_position = Vec2::new(bigfoot.x, bigfoot.y);
 
-                        // If the player is within the 250 radius, apply damage
+                        // If the player is within the stomp radius, apply damage and push them out of the footprint
                         let distance = player_position.distance(bigfoot_position);
-                        if distance <= 175.0 {
-                            //if let Some(ref mut invulnerability) = invulnerability_option {
-                            //    player.take_damage(
-                            //        500, // Damage amount
-                            //        entity,
-                            //        &mut commands,
-                            //        Some(invulnerability), // Pass the mutable reference
-                            //        &mut state,
-                            //        &asset_server
-                            //    );
-                            //} else {
-                            //    player.take_damage(
-                            //        500, // Damage amount
-                            //        entity,
-                            //        &mut commands,
-                            //        None, 
-                            //        &mut state,
-                            //        &asset_server
-                            //    );
-                            //}
+                        if distance <= BIGFOOT_STOMP_RADIUS {
+                            let direction = (player_position - bigfoot_position).try_normalize().unwrap_or(Vec2::X);
+
+                            player_hits.send(PlayerHit {
+                                amount: BIGFOOT_STOMP_DAMAGE,
+                                knockback: direction * (BIGFOOT_STOMP_RADIUS - distance + 25.0),
+                                invulnerability: 1.0,
+                            });
                         }
                     }
                 }
@@ -201,7 +219,7 @@ pub fn update_bigfoot(
                     // Bigfoot has finished stomping, reset its state and move it to the player's position
 
                     // Get the player's position
-                    if let Ok((player_transform, _invulnerability_option)) = player_query.get_single() {
+                    if let Ok(player_transform) = player_query.get_single() {
                         // Move Bigfoot to the player_velocity's position
                         bigfoot.x = player_transform.translation.x;
                         bigfoot.y = player_transform.translation.y;
@@ -219,11 +237,18 @@ pub fn update_bigfoot(
                         cycle_texture(&mut texture, &bigfoot);
                     }
                 }
-                BigfootState::Cleanup => todo!(),
+                BigfootState::Cleanup => {
+                    // Bigfoot has finished dying, the run is won
+                    commands.entity(entity).despawn_recursive();
+                    next_state.set(GameState::Won);
+                }
             }
         } else if bigfoot.state == BigfootState::Invulnerable {
             // While Bigfoot is invulnerable, make it semi-transparent
             sprite.color.set_alpha(0.5);
+        } else if bigfoot.state == BigfootState::Cleanup {
+            // Fade Bigfoot out while it dies
+            sprite.color.set_alpha(bigfoot.timer.fraction_remaining());
         }
     }
 }
@@ -232,10 +257,10 @@ fn cycle_texture(
     texture: &mut Handle<Image>,
     bigfoot: &Bigfoot,
 ) {
-    if *texture == bigfoot.airTexture {
-        *texture = bigfoot.groundTexture.clone();
+    if *texture == bigfoot.air_texture {
+        *texture = bigfoot.ground_texture.clone();
     } else {
-        *texture = bigfoot.airTexture.clone();
+        *texture = bigfoot.air_texture.clone();
     }
 }
 
@@ -255,7 +280,9 @@ pub fn update_mouse_position(
     mut mouse_position: ResMut<MouseCoords>,
     camera_query: Query<(&GlobalTransform, &OrthographicProjection), With<Camera2d>>,
 ) {
-    let window = q_windows.single();
+    let Ok(window) = q_windows.get_single() else {
+        return;
+    };
 
     if let Some(cursor_position) = window.cursor_position() {
         if let Ok((camera_transform, projection)) = camera_query.get_single() {
@@ -286,13 +313,8 @@ pub fn manage_invulnerability(
 ) {
     for (entity, mut invulnerability) in query.iter_mut() {
         invulnerability.timer.tick(time.delta());
-        println!(
-            "Invulnerability timer ticking for entity {:?}, remaining: {:.2}",
-            entity,
-            invulnerability.timer.remaining_secs()
-        );
         if invulnerability.timer.finished() {
-            println!("Invulnerability expired for entity {:?}", entity);
+            debug!("Invulnerability expired for entity {:?}", entity);
             commands.entity(entity).remove::<Invulnerability>(); // Remove the component when the timer is done
         }
     }
@@ -303,9 +325,7 @@ pub fn update_cooldowns(
     mut query: Query<&mut Cooldowns>,
 ) {
     for mut cooldowns in query.iter_mut() {
-        for timer in cooldowns.cooldowns.values_mut() {
-            timer.tick(time.delta());
-        }
+        cooldowns.tick(time.delta());
     }
 }
 
@@ -324,21 +344,24 @@ pub fn update_lifetime(
 }
 
 pub fn update_cooldowns_ui(
-    mut cooldowns_query: Query<&mut Cooldowns>,
-    mut text_query: Query<&mut Text, With<CooldownUi>>,
+    cooldowns_query: Query<&Cooldowns, With<Player>>,
+    defs: Res<Assets<AbilityDef>>,
+    mut text_query: Query<(&mut Text, &CooldownUi)>,
 ) {
-    if let Ok(cooldowns) = cooldowns_query.get_single_mut() {
+    if let Ok(cooldowns) = cooldowns_query.get_single() {
         // Update the UI text for each ability
-        for (i, mut text) in text_query.iter_mut().enumerate() {
-            let ability_text = match i {
-                0 => format_cooldown_text("Attack", cooldowns.get_cooldown(Ability::Attack)),
-                1 => format_cooldown_text("Ranged", cooldowns.get_cooldown(Ability::Ranged)),
-                2 => format_cooldown_text("Dash", cooldowns.get_cooldown(Ability::Dash)),
-                3 => format_cooldown_text("Aoe", cooldowns.get_cooldown(Ability::Aoe)),
-                _ => "Unknown Ability".to_string(),
+        for (mut text, CooldownUi(ability)) in text_query.iter_mut() {
+            let Some(def) = defs.get(*ability) else {
+                continue;
             };
 
-            text.sections[0].value = ability_text;
+            text.sections[0].value = match cooldowns.get_charges(*ability) {
+                Some(charges) => format_cooldown_text(
+                    &format!("{} x{}", def.name, charges.stored),
+                    cooldowns.get_cooldown(*ability).filter(|_| charges.stored < charges.max),
+                ),
+                None => format_cooldown_text(&def.name, cooldowns.get_cooldown(*ability)),
+            };
         }
     }
 }
@@ -364,7 +387,7 @@ pub fn update_ui_text(
             if health_text.is_some() {
                 text.sections[0].value = format!("Health: {}", player_health.hp);
             } else if score_text.is_some() {
-                text.sections[0].value = format!("Score: {}", score.get_enemies_killed());
+                text.sections[0].value = format!("Score: {}", score.get_points());
             }else if timer_text.is_some() {
                 text.sections[0].value = format!("Time: {}", f32::trunc(timer.0 * 100.0)/ 100.)
             }
@@ -448,29 +471,25 @@ pub fn death_sound(
     });
 }
 
-pub fn play_empty_swing(
-    asset_server: Res<AssetServer>,
-    commands: &mut Commands
+// Plays one of an ability's sounds, picked at random
+pub fn play_random_sfx(
+    asset_server: &Res<AssetServer>,
+    commands: &mut Commands,
+    sounds: &[String],
+    volume: f32,
+    rng: &mut impl Rng,
 ) {
-    let sound1 = "sfx/swing1.ogg";
-    let sound2 = "sfx/swing2.ogg";
-    let sound3 = "sfx/swing3.ogg";
-
-    // Collect the sounds into a vector
-    let sounds = vec![sound1, sound2, sound3];
+    if sounds.is_empty() {
+        return;
+    }
 
-    // Generate a random index to pick a sound
-    let mut rng = rand::thread_rng();
-    let random_index = rng.gen_range(0..sounds.len());
+    let selected_sound = &sounds[rng.gen_range(0..sounds.len())];
 
-    // Select the sound based on the random index
-    let selected_sound = sounds[random_index];
-    // Create an entity dedicated to playing our background music
     let _ = &mut commands.spawn(AudioBundle {
-        source: asset_server.load(selected_sound),
+        source: asset_server.load(selected_sound.clone()),
         settings: PlaybackSettings {
             mode: PlaybackMode::Once,
-            volume: Volume::new(1.0),
+            volume: Volume::new(volume),
             ..Default::default()
         }
     });
@@ -478,17 +497,17 @@ pub fn play_empty_swing(
 
 pub fn play_hit_swing(
     asset_server: & Res<AssetServer>,
-    commands: &mut Commands
+    commands: &mut Commands,
+    rng: &mut impl Rng,
 ) {
     let sound1 = "sfx/hit1.ogg";
     let sound2 = "sfx/hit2.ogg";
     let sound3 = "sfx/hit3.ogg";
 
     // Collect the sounds into a vector
-    let sounds = vec![sound1, sound2, sound3];
+    let sounds = [sound1, sound2, sound3];
 
     // Generate a random index to pick a sound
-    let mut rng = rand::thread_rng();
     let random_index = rng.gen_range(0..sounds.len());
 
     // Select the sound based on the random index
@@ -518,20 +537,6 @@ pub fn bone_hit(
     });
 }
 
-pub fn dash_sound(
-    asset_server: &Res<AssetServer>,
-    commands: &mut Commands
-) {
-    // Create an entity dedicated to playing our background music
-    let _ = &mut commands.spawn(AudioBundle {
-        source: asset_server.load("./sfx/dash.ogg"),
-        settings: PlaybackSettings {
-            mode: PlaybackMode::Once,
-            volume: Volume::new(2.75),
-            ..Default::default()
-        }
-    });
-}
 
 pub fn reset_game(
     mut commands: Commands,
@@ -539,9 +544,7 @@ pub fn reset_game(
     mut cooldowns_query: Query<&mut Cooldowns>,
     mut score: ResMut<Score>,
     mut game_timer: ResMut<GameTimer>,
-    mut points: ResMut<Points>,
     mut map_grid: ResMut<MapGrid>,
-    mut enemy_spawn_rate: ResMut<EnemySpawnRate>,
     mut next_state: ResMut<NextState<GameState>>,
 ) {
     for entity in resettable.iter() {
@@ -554,27 +557,11 @@ pub fn reset_game(
 
     score.reset();
     game_timer.0 = 0.0;
-    points.0.clear();
     map_grid.positions.clear();
-    enemy_spawn_rate.0 = 2.0;
 
     next_state.set(GameState::Running);
 }
 
-pub fn aoe_sound(
-    asset_server: &Res<AssetServer>,
-    commands: &mut Commands
-) {
-    // Create an entity dedicated to playing our background music
-    let _ = &mut commands.spawn(AudioBundle {
-        source: asset_server.load("./sfx/aoe.ogg"),
-        settings: PlaybackSettings {
-            mode: PlaybackMode::Once,
-            volume: Volume::new(0.8),
-            ..Default::default()
-        }
-    });
-}
 pub fn update_timer(
     time: Res<Time>,
     mut timer: ResMut<GameTimer>,
@@ -604,13 +591,13 @@ pub fn stomp_sound(
     });
 }
 
-pub fn ranged_sound(
-    asset_server: &mut Res<AssetServer>,
+
+pub fn boomerang_sound(
+    asset_server: &Res<AssetServer>,
     commands: &mut Commands
 ) {
-    // Create an entity dedicated to playing our background music
     let _ = &mut commands.spawn(AudioBundle {
-        source: asset_server.load("./sfx/ranged.ogg"),
+        source: asset_server.load("./sfx/boomerang 2.ogg"),
         settings: PlaybackSettings {
             mode: PlaybackMode::Once,
             volume: Volume::new(0.6),
@@ -659,20 +646,69 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, mut state:
         map: asset_server.load(MAP_SPIRITE),
     };
 
-    let enemy_count = EnemySpawnRate(2.0);
-
     let mouse_coords = MouseCoords {
         x: 0.,
         y: 0.,
     };
 
-    // Create an entity dedicated to playing our background music
-    commands.spawn(AudioBundle {
-        source: asset_server.load("./beats/back.ogg"),
-        settings: PlaybackSettings::LOOP,
-    });
     commands.insert_resource(game_textures);
-    commands.insert_resource(enemy_count);
     commands.insert_resource(mouse_coords);
-    commands.insert_resource(Points::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_event::<PlayerHit>()
+            .add_systems(Update, apply_player_damage);
+        app
+    }
+
+    fn stomp() -> PlayerHit {
+        PlayerHit {
+            amount: BIGFOOT_STOMP_DAMAGE,
+            knockback: Vec2::new(50., 0.),
+            invulnerability: 1.0,
+        }
+    }
+
+    #[test]
+    fn hit_damages_knocks_back_and_grants_invulnerability() {
+        let mut app = test_app();
+        let player = app.world_mut().spawn((Player, Health { hp: 500 }, Transform::default())).id();
+
+        app.world_mut().send_event(stomp());
+        app.world_mut().send_event(stomp());
+        app.update();
+
+        let world = app.world();
+        assert_eq!(world.get::<Health>(player).unwrap().hp, 500 - BIGFOOT_STOMP_DAMAGE);
+        assert_eq!(world.get::<Transform>(player).unwrap().translation.x, 50.);
+        assert!(world.get::<Invulnerability>(player).is_some());
+    }
+
+    #[test]
+    fn invulnerable_player_ignores_hits() {
+        let mut app = test_app();
+        let player = app
+            .world_mut()
+            .spawn((
+                Player,
+                Health { hp: 500 },
+                Transform::default(),
+                Invulnerability {
+                    timer: Timer::from_seconds(1.0, TimerMode::Once),
+                },
+            ))
+            .id();
+
+        app.world_mut().send_event(stomp());
+        app.update();
+
+        assert_eq!(app.world().get::<Health>(player).unwrap().hp, 500);
+        assert_eq!(app.world().get::<Transform>(player).unwrap().translation, Vec3::ZERO);
+    }
 }
@@ -0,0 +1,324 @@
+use std::{f32::consts::PI, fmt, fs, time::Duration};
+
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    components::{EnemyKind, SpawnTimer},
+    PLAYER_RADIUS,
+};
+
+const WAVE_SCRIPT_PATH: &str = "assets/waves.ron";
+// Shipped copy of the script, used when the file on disk is missing or broken
+const DEFAULT_WAVE_SCRIPT: &str = include_str!("../assets/waves.ron");
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Formation {
+    Single,
+    Ring { count: usize },
+    Line { count: usize, spacing: f32 },
+    Cluster { count: usize, spread: f32 },
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Wave {
+    pub start: f32,               // Seconds into the run
+    pub end: Option<f32>,         // None keeps the wave running forever
+    pub spawn_interval: f32,
+    #[serde(default)]
+    pub interval_decrease: f32,   // Seconds taken off the interval per second
+    #[serde(default = "default_min_interval")]
+    pub min_interval: f32,
+    #[serde(default = "default_ring_radius")]
+    pub ring_radius: f32,
+    pub max_alive: usize,
+    pub mix: Vec<(EnemyKind, f32)>,          // Weighted archetypes
+    pub formations: Vec<(Formation, f32)>,   // Weighted spawn patterns
+}
+
+fn default_min_interval() -> f32 {
+    0.05
+}
+
+fn default_ring_radius() -> f32 {
+    PLAYER_RADIUS
+}
+
+impl Wave {
+    fn contains(&self, time: f32) -> bool {
+        time >= self.start && self.end.is_none_or(|end| time < end)
+    }
+
+    // Intervals feed straight into Durations and the catch-up loop, so they have to be above 0
+    fn validate(&self) -> Result<(), String> {
+        let positive = |value: f32| value.is_finite() && value > 0.0;
+        let not_negative = |value: f32| value.is_finite() && value >= 0.0;
+
+        if !not_negative(self.start) || self.end.is_some_and(|end| !(end.is_finite() && end > self.start)) {
+            return Err("end must come after start".to_string());
+        }
+        if !positive(self.spawn_interval) || !positive(self.min_interval) {
+            return Err("spawn_interval and min_interval must be above 0".to_string());
+        }
+        if !not_negative(self.interval_decrease) || !positive(self.ring_radius) {
+            return Err("interval_decrease can't be negative and ring_radius must be above 0".to_string());
+        }
+        for (formation, _) in self.formations.iter() {
+            if let Formation::Line { spacing: size, .. } | Formation::Cluster { spread: size, .. } = formation {
+                if !not_negative(*size) {
+                    return Err("formation spacing and spread can't be negative".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn spawn_timer(&self) -> SpawnTimer {
+        SpawnTimer::new(
+            Duration::from_secs_f32(self.spawn_interval),
+            self.interval_decrease,
+            Duration::from_secs_f32(self.min_interval),
+        )
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct WaveScript {
+    pub waves: Vec<Wave>,
+}
+
+#[derive(Debug)]
+pub enum WaveScriptError {
+    Ron(ron::error::SpannedError),
+    Invalid { wave: usize, problem: String },
+}
+
+impl fmt::Display for WaveScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaveScriptError::Ron(error) => write!(f, "{}", error),
+            WaveScriptError::Invalid { wave, problem } => write!(f, "wave {}: {}", wave + 1, problem),
+        }
+    }
+}
+
+impl std::error::Error for WaveScriptError {}
+
+impl WaveScript {
+    pub fn parse(source: &str) -> Result<Self, WaveScriptError> {
+        let script: Self = ron::from_str(source).map_err(WaveScriptError::Ron)?;
+        for (wave, entry) in script.waves.iter().enumerate() {
+            entry.validate().map_err(|problem| WaveScriptError::Invalid { wave, problem })?;
+        }
+        Ok(script)
+    }
+
+    // Reads the designer-editable script, falling back to the built-in one
+    pub fn load() -> Self {
+        match fs::read_to_string(WAVE_SCRIPT_PATH).map(|source| Self::parse(&source)) {
+            Ok(Ok(script)) => script,
+            Ok(Err(error)) => {
+                warn!("Invalid wave script {}: {}, using default", WAVE_SCRIPT_PATH, error);
+                Self::default()
+            }
+            Err(error) => {
+                warn!("Could not read {}: {}, using default", WAVE_SCRIPT_PATH, error);
+                Self::default()
+            }
+        }
+    }
+}
+
+impl Default for WaveScript {
+    fn default() -> Self {
+        Self::parse(DEFAULT_WAVE_SCRIPT).expect("built-in wave script is invalid")
+    }
+}
+
+// A single enemy the director wants spawned, relative to the player
+pub struct SpawnOrder {
+    pub kind: EnemyKind,
+    pub offset: Vec2,
+}
+
+#[derive(Resource)]
+pub struct WaveDirector {
+    pub script: WaveScript,
+    pub elapsed: f32,
+    current: Option<usize>,
+    spawn_timer: Option<SpawnTimer>,
+}
+
+impl WaveDirector {
+    pub fn new(script: WaveScript) -> Self {
+        Self {
+            script,
+            elapsed: 0.0,
+            current: None,
+            spawn_timer: None,
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.elapsed = 0.0;
+        self.current = None;
+        self.spawn_timer = None;
+    }
+
+    // Advances the script and returns everything that should spawn this tick
+    pub fn tick(&mut self, delta: Duration, alive: usize, rng: &mut impl Rng) -> Vec<SpawnOrder> {
+        self.elapsed += delta.as_secs_f32();
+
+        let current = self.script.waves.iter().rposition(|wave| wave.contains(self.elapsed));
+        if current != self.current {
+            if let Some(index) = current {
+                info!("Wave {} started at {:.1}s", index + 1, self.elapsed);
+            }
+            self.current = current;
+            self.spawn_timer = current.map(|index| self.script.waves[index].spawn_timer());
+        }
+
+        let (Some(index), Some(spawn_timer)) = (self.current, self.spawn_timer.as_mut()) else {
+            return Vec::new();
+        };
+        let wave = &self.script.waves[index];
+
+        spawn_timer.update(delta);
+
+        let mut orders = Vec::new();
+        for _ in 0..spawn_timer.timer.times_finished_this_tick() {
+            let room = wave.max_alive.saturating_sub(alive + orders.len());
+            if room == 0 {
+                break;
+            }
+
+            let formation = pick_weighted(&wave.formations, rng).unwrap_or(Formation::Single);
+            for offset in formation_offsets(formation, wave.ring_radius, rng).into_iter().take(room) {
+                if let Some(kind) = pick_weighted(&wave.mix, rng) {
+                    orders.push(SpawnOrder { kind, offset });
+                }
+            }
+        }
+
+        orders
+    }
+}
+
+fn pick_weighted<T: Copy>(entries: &[(T, f32)], rng: &mut impl Rng) -> Option<T> {
+    let total: f32 = entries.iter().map(|(_, weight)| weight.max(0.0)).sum();
+    if total <= 0.0 {
+        return None;
+    }
+
+    let mut roll = rng.gen_range(0.0..total);
+    for (entry, weight) in entries {
+        let weight = weight.max(0.0);
+        if roll < weight {
+            return Some(*entry);
+        }
+        roll -= weight;
+    }
+
+    entries.last().map(|(entry, _)| *entry)
+}
+
+// Positions for a formation, relative to the player and centred on the spawn ring
+pub fn formation_offsets(formation: Formation, radius: f32, rng: &mut impl Rng) -> Vec<Vec2> {
+    let angle = rng.gen_range(0.0..(2.0 * PI));
+    let anchor = Vec2::from_angle(angle) * radius;
+
+    match formation {
+        Formation::Single => vec![anchor],
+        Formation::Ring { count } => (0..count)
+            .map(|i| Vec2::from_angle(angle + i as f32 * 2.0 * PI / count as f32) * radius)
+            .collect(),
+        Formation::Line { count, spacing } => {
+            // Line up across the approach direction so the whole row advances together
+            let across = anchor.normalize_or(Vec2::X).perp();
+            let half_width = (count.saturating_sub(1)) as f32 * spacing / 2.0;
+            (0..count)
+                .map(|i| anchor + across * (i as f32 * spacing - half_width))
+                .collect()
+        }
+        Formation::Cluster { count, spread } => (0..count)
+            .map(|_| {
+                let offset_angle = rng.gen_range(0.0..(2.0 * PI));
+                let offset_radius = spread * rng.gen_range(0.0f32..1.0).sqrt();
+                anchor + Vec2::from_angle(offset_angle) * offset_radius
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    const SCRIPT: &str = "(
+        waves: [
+            (start: 0.0, end: Some(10.0), spawn_interval: 1.0, max_alive: 5, mix: [(Oni, 1.0)], formations: [(Single, 1.0)]),
+            (start: 20.0, end: None, spawn_interval: 1.0, ring_radius: 100.0, max_alive: 50, mix: [(Oni, 1.0)], formations: [(Ring(count: 8), 1.0)]),
+        ],
+    )";
+
+    fn tick_for(director: &mut WaveDirector, seconds: u32, rng: &mut StdRng) -> usize {
+        (0..seconds)
+            .map(|_| director.tick(Duration::from_secs(1), 0, rng).len())
+            .sum()
+    }
+
+    #[test]
+    fn built_in_script_parses() {
+        assert!(!WaveScript::default().waves.is_empty());
+    }
+
+    #[test]
+    fn waves_follow_their_time_windows() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut director = WaveDirector::new(WaveScript::parse(SCRIPT).unwrap());
+
+        assert_eq!(tick_for(&mut director, 9, &mut rng), 9);
+        // Gap between the two waves spawns nothing
+        assert_eq!(tick_for(&mut director, 10, &mut rng), 0);
+        assert_eq!(director.current, None);
+
+        let orders = director.tick(Duration::from_secs(1), 0, &mut rng);
+        assert_eq!(director.current, Some(1));
+        assert_eq!(orders.len(), 8);
+        assert!(orders.iter().all(|order| (order.offset.length() - 100.0).abs() < 1e-3));
+    }
+
+    #[test]
+    fn respects_max_alive() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut director = WaveDirector::new(WaveScript::parse(SCRIPT).unwrap());
+        director.elapsed = 20.0;
+
+        assert_eq!(director.tick(Duration::from_secs(1), 45, &mut rng).len(), 5);
+    }
+
+    #[test]
+    fn line_formation_is_centred_on_the_ring() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let offsets = formation_offsets(Formation::Line { count: 5, spacing: 10.0 }, 200.0, &mut rng);
+
+        assert_eq!(offsets.len(), 5);
+        assert!((offsets[2].length() - 200.0).abs() < 1e-3);
+        assert!((offsets[0].distance(offsets[4]) - 40.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn intervals_that_would_hang_or_panic_are_refused() {
+        for broken in ["spawn_interval: 0.0", "spawn_interval: -1.0", "spawn_interval: 1.0, min_interval: -0.5"] {
+            let script = SCRIPT.replacen("spawn_interval: 1.0", broken, 1);
+            assert!(matches!(WaveScript::parse(&script), Err(WaveScriptError::Invalid { wave: 0, .. })), "{}", broken);
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let offsets = formation_offsets(Formation::Line { count: 3, spacing: 10.0 }, 0.0, &mut rng);
+        assert!(offsets.iter().all(|offset| offset.is_finite()));
+    }
+}